@@ -0,0 +1,174 @@
+//! Compares the assignment strategies on `make_blobs` data of varying size,
+//! cluster count and dimensionality:
+//!
+//! - `polars`: Lloyd iterations as Polars expressions,
+//! - `sparse`: the Rust CSR distance path of [`KMeans::with_sparse`],
+//! - `elkan`: [`Algorithm::Elkan`] with triangle-inequality bounds,
+//! - `auto`: the default [`Algorithm::Auto`], which picks `elkan` or
+//!   `polars` from the data shape,
+//! - `native`: the Polars-free [`native::fit`] loop.
+//!
+//! Every strategy starts from the same seed and normally reaches the same
+//! partition, so the timings compare like with like. At `k = 20` the number
+//! of point-to-centroid distances Elkan and Lloyd evaluate is printed as
+//! well. The `kernel` group times the squared-distance kernel alone on 100k
+//! rows of 128 features, scalar against SIMD when built with
+//! `--features simd`. Run with `cargo bench`.
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use kmeans::{
+    make_blobs, native, squared_euclidean_scalar, Algorithm, DistanceMetric, InitMethod, KMeans,
+};
+use polars::prelude::*;
+
+const SEED: u64 = 7;
+
+fn points(lf: &LazyFrame) -> Vec<Vec<f64>> {
+    let df = lf
+        .clone()
+        .select([col("*").exclude(["n"])])
+        .collect()
+        .unwrap();
+    let columns = df
+        .iter()
+        .map(|s| s.f64().unwrap().into_no_null_iter().collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+
+    (0..df.height())
+        .map(|i| columns.iter().map(|c| c[i]).collect())
+        .collect()
+}
+
+fn assignment(c: &mut Criterion) {
+    for n_samples in [1_000, 10_000] {
+        for n_features in [2, 16] {
+            for k in [4, 16, 20, 30] {
+                let (lf, _) = make_blobs(n_samples, n_features, k, 1.0, SEED);
+                let lf = lf.collect().unwrap().lazy();
+                let rows = points(&lf);
+
+                let mut group = c.benchmark_group(format!("n{n_samples}_d{n_features}"));
+                group.sample_size(10);
+
+                let kmeans = |k: usize| {
+                    KMeans::new(lf.clone(), k, None, false, CsvWriterOptions::default())
+                        .unwrap()
+                        .with_seed(SEED)
+                };
+
+                group.bench_with_input(BenchmarkId::new("polars", k), &k, |b, &k| {
+                    b.iter(|| kmeans(k).with_algorithm(Algorithm::Lloyd).fit())
+                });
+                group.bench_with_input(BenchmarkId::new("auto", k), &k, |b, &k| {
+                    b.iter(|| kmeans(k).fit())
+                });
+                group.bench_with_input(BenchmarkId::new("sparse", k), &k, |b, &k| {
+                    b.iter(|| kmeans(k).with_sparse(true).fit())
+                });
+                group.bench_with_input(BenchmarkId::new("elkan", k), &k, |b, &k| {
+                    b.iter(|| kmeans(k).with_algorithm(Algorithm::Elkan).fit())
+                });
+                group.bench_with_input(BenchmarkId::new("native", k), &k, |b, &k| {
+                    b.iter(|| {
+                        native::fit(
+                            &rows,
+                            k,
+                            InitMethod::default(),
+                            DistanceMetric::Euclidean,
+                            Some(SEED),
+                        )
+                        .unwrap()
+                    })
+                });
+
+                group.finish();
+            }
+        }
+    }
+}
+
+/// Runs native iterations from `centers` until the labels repeat, with
+/// `assign` labelling the points each step. Returns the iteration count and
+/// the distances a full Lloyd scan evaluates over them; Elkan reports its
+/// own count.
+fn iterate(
+    rows: &[Vec<f64>],
+    mut centers: Vec<Vec<f64>>,
+    mut assign: impl FnMut(&[Vec<f64>]) -> Vec<u64>,
+) -> (usize, usize) {
+    let mut labels_last = None;
+    let mut iterations = 0;
+    let mut evaluations = 0;
+
+    loop {
+        let labels = assign(&centers);
+        iterations += 1;
+        evaluations += rows.len() * centers.len();
+        if labels_last.as_ref() == Some(&labels) {
+            return (iterations, evaluations);
+        }
+
+        (_, centers) = native::update_centers(rows, &labels);
+        labels_last = Some(labels);
+    }
+}
+
+fn evaluations(_: &mut Criterion) {
+    let k = 20;
+    for n_samples in [1_000, 10_000] {
+        for n_features in [2, 16] {
+            let (lf, _) = make_blobs(n_samples, n_features, k, 1.0, SEED);
+            let rows = points(&lf);
+            let centers = rows
+                .iter()
+                .step_by(n_samples / k)
+                .cloned()
+                .collect::<Vec<_>>();
+
+            let (iterations, lloyd) = iterate(&rows, centers.clone(), |centers| {
+                native::assign(&rows, centers, DistanceMetric::Euclidean)
+            });
+            let mut elkan = native::Elkan::new(rows.clone());
+            iterate(&rows, centers, |centers| elkan.assign(centers));
+
+            println!(
+                "n{n_samples}_d{n_features} k={k}: {iterations} iterations, \
+                 lloyd {lloyd} distances, elkan {} ({:.1}%)",
+                elkan.evaluations(),
+                100.0 * elkan.evaluations() as f64 / lloyd as f64
+            );
+        }
+    }
+}
+
+fn kernel(c: &mut Criterion) {
+    let (lf, _) = make_blobs(100_000, 128, 8, 1.0, SEED);
+    let rows = points(&lf);
+    let centers = rows
+        .iter()
+        .step_by(100_000 / 8)
+        .cloned()
+        .collect::<Vec<_>>();
+    let total = |kernel: fn(&[f64], &[f64]) -> f64| {
+        rows.iter()
+            .flat_map(|p| centers.iter().map(move |c| kernel(black_box(p), c)))
+            .sum::<f64>()
+    };
+
+    let mut group = c.benchmark_group("kernel_n100000_d128");
+    group.sample_size(10);
+    group.bench_function("scalar", |b| b.iter(|| total(squared_euclidean_scalar)));
+    #[cfg(feature = "simd")]
+    {
+        let (scalar, simd) = (
+            total(squared_euclidean_scalar),
+            total(kmeans::squared_euclidean_simd),
+        );
+        assert!((scalar - simd).abs() <= 1e-9 * scalar);
+        group.bench_function("simd", |b| b.iter(|| total(kmeans::squared_euclidean_simd)));
+    }
+    group.finish();
+}
+
+criterion_group!(benches, assignment, evaluations, kernel);
+criterion_main!(benches);
@@ -0,0 +1,513 @@
+use polars::prelude::*;
+
+use crate::{
+    hull_measure, metrics::points, squared_euclidean, Error, FitResult, InitMethod, KMeans,
+};
+
+/// Flattens cluster frames back into `(index, cluster)` pairs sorted by the
+/// `index_col` value, the inverse of partitioning. Panics if an index
+/// appears in more than one cluster or twice in one.
+pub fn into_labels(clusters: &[(u64, LazyFrame)], index_col: &str) -> Vec<(u64, u64)> {
+    let mut labels = clusters
+        .iter()
+        .flat_map(|(id, lf)| {
+            let ids = lf
+                .clone()
+                .select([col(index_col).cast(DataType::UInt64)])
+                .collect()
+                .unwrap();
+            ids.column(index_col)
+                .unwrap()
+                .u64()
+                .unwrap()
+                .into_no_null_iter()
+                .map(|n| (n, *id))
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+    labels.sort_unstable();
+
+    if let Some(pair) = labels.windows(2).find(|pair| pair[0].0 == pair[1].0) {
+        panic!("{index_col} = {} appears more than once", pair[0].0);
+    }
+
+    labels
+}
+
+/// Adds a `<feature>_contribution` column to every cluster of `result`
+/// holding the squared difference between the point and its fitted centroid
+/// along that feature, plus their sum in `sqdist`, the total squared
+/// distance. Frames and centroids are both in fitted space, so scaling and
+/// PCA are already applied, and a fit stopped before convergence is
+/// measured against the centroids it returned rather than the cluster means.
+pub fn feature_contributions(result: &FitResult) -> Vec<(u64, LazyFrame)> {
+    result
+        .clusters
+        .iter()
+        .zip(&result.centers)
+        .map(|((id, lf), center)| {
+            let features = lf
+                .schema()
+                .unwrap()
+                .iter_names()
+                .filter(|name| name.as_str() != "n")
+                .map(|name| name.to_string())
+                .collect::<Vec<_>>();
+
+            let contributions = features
+                .iter()
+                .zip(center)
+                .map(|(f, c)| (col(f) - lit(*c)).pow(2))
+                .collect::<Vec<_>>();
+
+            let mut exprs = features
+                .iter()
+                .zip(&contributions)
+                .map(|(f, e)| e.clone().alias(format!("{f}_contribution").as_str()))
+                .collect::<Vec<_>>();
+            exprs.push(
+                contributions
+                    .into_iter()
+                    .reduce(|acc, e| acc + e)
+                    .unwrap()
+                    .alias("sqdist"),
+            );
+
+            (*id, lf.clone().with_columns(exprs))
+        })
+        .collect()
+}
+
+/// Euclidean distance from every point to its nearest neighbour in the same
+/// cluster, in the row order of each cluster frame. Points alone in their
+/// cluster have no neighbour and get infinity.
+pub fn nearest_neighbor_distances(clusters: &[(u64, LazyFrame)]) -> Vec<(u64, Vec<f64>)> {
+    clusters
+        .iter()
+        .map(|(id, lf)| {
+            let points = points(lf);
+            let mut nearest = vec![f64::INFINITY; points.len()];
+
+            for (i, p) in points.iter().enumerate() {
+                for (j, q) in points.iter().enumerate().skip(i + 1) {
+                    let d = squared_euclidean(p, q);
+                    nearest[i] = nearest[i].min(d);
+                    nearest[j] = nearest[j].min(d);
+                }
+            }
+
+            (*id, nearest.into_iter().map(f64::sqrt).collect())
+        })
+        .collect()
+}
+
+/// Scores how well each feature separates `clusters` with the univariate
+/// ANOVA F statistic: between-cluster variance over within-cluster variance.
+/// Higher means more discriminative. Returned sorted by descending score; a
+/// feature that is constant within every cluster but differs between them
+/// scores infinity.
+pub fn feature_importance(clusters: &[(u64, LazyFrame)]) -> Vec<(String, f64)> {
+    let Some((_, first)) = clusters.first() else {
+        return Vec::new();
+    };
+    let features = first
+        .schema()
+        .unwrap()
+        .iter_names()
+        .filter(|name| name.as_str() != "n")
+        .map(|name| name.to_string())
+        .collect::<Vec<_>>();
+
+    // Per cluster: row count, then the mean and the sum of squared
+    // deviations of every feature.
+    let stats = clusters
+        .iter()
+        .map(|(_, lf)| {
+            let mut exprs = vec![col("n").count().cast(DataType::Float64)];
+            for f in &features {
+                exprs.push(col(f).mean().alias(&format!("{f}_mean")));
+                exprs.push(
+                    (col(f) - col(f).mean())
+                        .pow(2)
+                        .sum()
+                        .alias(&format!("{f}_ss")),
+                );
+            }
+            let row = lf.clone().select(exprs).collect().unwrap();
+
+            row.get_columns()
+                .iter()
+                .map(|s| {
+                    s.cast(&DataType::Float64)
+                        .unwrap()
+                        .f64()
+                        .unwrap()
+                        .get(0)
+                        .unwrap_or(0.0)
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+
+    let n_total = stats.iter().map(|s| s[0]).sum::<f64>();
+    let k = stats.len() as f64;
+
+    let mut scores = features
+        .into_iter()
+        .enumerate()
+        .map(|(j, feature)| {
+            let mean = stats.iter().map(|s| s[0] * s[1 + 2 * j]).sum::<f64>() / n_total;
+            let between = stats
+                .iter()
+                .map(|s| s[0] * (s[1 + 2 * j] - mean).powi(2))
+                .sum::<f64>()
+                / (k - 1.0);
+            let within = stats.iter().map(|s| s[2 + 2 * j]).sum::<f64>() / (n_total - k);
+
+            let score = if within > 0.0 {
+                between / within
+            } else if between > 0.0 {
+                f64::INFINITY
+            } else {
+                0.0
+            };
+
+            (feature, score)
+        })
+        .collect::<Vec<_>>();
+
+    scores.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+
+    scores
+}
+
+/// Which pair [`merge_close_clusters_with`] merges next and what its
+/// `threshold` bounds.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum MergeCriterion {
+    /// The pair of closest centroids, while their Euclidean distance is
+    /// below the threshold. Blind to cluster sizes, so a large cluster keeps
+    /// absorbing small neighbours.
+    #[default]
+    Centroid,
+    /// Ward's criterion: the pair whose union increases the total
+    /// within-cluster sum of squares the least, while that increase is below
+    /// the threshold. The increase, `n_i n_j / (n_i + n_j)` times the squared
+    /// centroid distance, grows with both sizes, which favours merging small
+    /// clusters and gives more balanced results.
+    Ward,
+}
+
+/// Agglomerative post-step: repeatedly merges the two closest centroids
+/// while their Euclidean distance is below `threshold`. The merged centroid
+/// is the size-weighted mean of the pair, its standard deviations are
+/// pooled from both, and the merged cluster keeps its rows ordered by `n`.
+/// Surviving clusters are renumbered `0..k` in their original order.
+/// Validation rows are not carried over.
+pub fn merge_close_clusters(result: FitResult, threshold: f64) -> FitResult {
+    merge_close_clusters_with(result, threshold, MergeCriterion::Centroid)
+}
+
+/// [`merge_close_clusters`] with a choice of [`MergeCriterion`]; under
+/// [`MergeCriterion::Ward`] `threshold` is in squared feature units.
+pub fn merge_close_clusters_with(
+    result: FitResult,
+    threshold: f64,
+    criterion: MergeCriterion,
+) -> FitResult {
+    let sizes = result
+        .clusters
+        .iter()
+        .map(|(_, lf)| lf.clone().collect().unwrap().height() as f64)
+        .collect::<Vec<_>>();
+
+    let mut merged = result
+        .clusters
+        .into_iter()
+        .zip(result.centers)
+        .zip(sizes)
+        .map(|(((_, lf), center), size)| (lf, center, size))
+        .collect::<Vec<_>>();
+    let mut std_devs = result.std_devs;
+
+    loop {
+        let mut closest: Option<(f64, usize, usize)> = None;
+        for i in 0..merged.len() {
+            for j in i + 1..merged.len() {
+                let (_, center_i, size_i) = &merged[i];
+                let (_, center_j, size_j) = &merged[j];
+                let dist = match criterion {
+                    MergeCriterion::Centroid => squared_euclidean(center_i, center_j).sqrt(),
+                    MergeCriterion::Ward => {
+                        size_i * size_j / (size_i + size_j) * squared_euclidean(center_i, center_j)
+                    }
+                };
+                if dist < threshold && closest.is_none_or(|(best, _, _)| dist < best) {
+                    closest = Some((dist, i, j));
+                }
+            }
+        }
+
+        let Some((_, i, j)) = closest else {
+            break;
+        };
+
+        let (lf_j, center_j, size_j) = merged.remove(j);
+        let std_j = std_devs.remove(j);
+        let (lf_i, center_i, size_i) = &mut merged[i];
+        let size = *size_i + size_j;
+        // Each side's variance about the merged mean is its own variance
+        // plus the squared shift of its mean.
+        for (((c, s), cj), sj) in center_i
+            .iter_mut()
+            .zip(std_devs[i].iter_mut())
+            .zip(&center_j)
+            .zip(&std_j)
+        {
+            let mean = (*c * *size_i + cj * size_j) / size;
+            let var = (*size_i * (s.powi(2) + (*c - mean).powi(2))
+                + size_j * (sj.powi(2) + (cj - mean).powi(2)))
+                / size;
+            *c = mean;
+            *s = var.sqrt();
+        }
+        *size_i = size;
+        *lf_i = concat([lf_i.clone(), lf_j], UnionArgs::default())
+            .unwrap()
+            .sort("n", SortOptions::default());
+    }
+
+    let (clusters, centers) = merged
+        .into_iter()
+        .enumerate()
+        .map(|(id, (lf, center, _))| ((id as u64, lf), center))
+        .unzip();
+
+    FitResult {
+        clusters,
+        centers,
+        std_devs,
+        stability: result.stability,
+        requested_k: result.requested_k,
+        validation: None,
+        selection_score: None,
+        size_history: result.size_history,
+    }
+}
+
+/// Divisive counterpart of [`merge_close_clusters`]: refits cluster
+/// `cluster_id` alone as a seeded Euclidean 2-means and leaves every other
+/// cluster untouched. One half keeps `cluster_id`, the other is appended
+/// under the largest cluster id plus one, so one more cluster is found while
+/// [`FitResult::requested_k`] stays as it was. Validation rows are
+/// not carried over. The halves are seeded with k-means++, which never
+/// picks a duplicate of the first seed while a distinct point is left.
+/// Should the split still end in a single cluster, `result` is returned
+/// unchanged with a warning.
+///
+/// Fails with [`Error::UnknownCenterIds`] if `cluster_id` is not in `result`
+/// and with [`Error::TooFewDistinctPoints`] if its cluster has fewer than
+/// two distinct points.
+pub fn split_cluster(result: FitResult, cluster_id: u64, seed: u64) -> Result<FitResult, Error> {
+    let i = result
+        .clusters
+        .iter()
+        .position(|(id, _)| *id == cluster_id)
+        .ok_or_else(|| Error::UnknownCenterIds(vec![cluster_id]))?;
+    let rows = points(&result.clusters[i].1);
+    if !rows.iter().any(|row| *row != rows[0]) {
+        return Err(Error::TooFewDistinctPoints {
+            k: 2,
+            distinct: usize::from(!rows.is_empty()),
+        });
+    }
+
+    let halves = KMeans::new(
+        result.clusters[i].1.clone(),
+        2usize,
+        None,
+        false,
+        CsvWriterOptions::default(),
+    )?
+    .with_init(InitMethod::kmeans_plus_plus())
+    .with_seed(seed)
+    .try_fit()?;
+    if halves.clusters.len() < 2 {
+        eprintln!("warning: cluster {cluster_id} did not split, leaving it unchanged");
+        return Ok(result);
+    }
+
+    let mut clusters = result.clusters;
+    let mut centers = result.centers;
+    let mut std_devs = result.std_devs;
+    let new_id = clusters.iter().map(|(id, _)| id + 1).max().unwrap_or(0);
+    for (half, ((_, lf), (center, std_dev))) in halves
+        .clusters
+        .into_iter()
+        .zip(halves.centers.into_iter().zip(halves.std_devs))
+        .enumerate()
+    {
+        if half == 0 {
+            clusters[i].1 = lf;
+            centers[i] = center;
+            std_devs[i] = std_dev;
+        } else {
+            clusters.push((new_id, lf));
+            centers.push(center);
+            std_devs.push(std_dev);
+        }
+    }
+
+    Ok(FitResult {
+        clusters,
+        centers,
+        std_devs,
+        stability: result.stability,
+        requested_k: result.requested_k,
+        validation: None,
+        selection_score: None,
+        size_history: result.size_history,
+    })
+}
+
+/// Spatial extent of every cluster: the convex hull area of its points for
+/// 2 features, the hull volume for 3. Single-point, collinear or coplanar
+/// clusters have extent 0.
+///
+/// Panics unless the clusters have 2 or 3 feature columns.
+pub fn cluster_hull_measures(clusters: &[(u64, LazyFrame)]) -> Vec<(u64, f64)> {
+    clusters
+        .iter()
+        .map(|(id, lf)| (*id, hull_measure(&points(lf))))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn clusters(columns: &[(u64, Vec<f64>)]) -> Vec<(u64, LazyFrame)> {
+        columns
+            .iter()
+            .map(|(id, x)| {
+                let n = (0..x.len() as u64)
+                    .map(|i| id * 100 + i)
+                    .collect::<Vec<_>>();
+                let df = DataFrame::new(vec![Series::new("n", n), Series::new("x", x)]);
+                (*id, df.unwrap().lazy())
+            })
+            .collect()
+    }
+
+    fn result(clusters: Vec<(u64, LazyFrame)>, centers: Vec<Vec<f64>>) -> FitResult {
+        FitResult {
+            requested_k: clusters.len(),
+            std_devs: vec![vec![0.0]; clusters.len()],
+            clusters,
+            centers,
+            stability: None,
+            validation: None,
+            selection_score: None,
+            size_history: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn contributions_are_measured_against_the_fitted_centroid() {
+        let fitted = result(clusters(&[(0, vec![0.0, 2.0])]), vec![vec![0.5]]);
+        let contributions = feature_contributions(&fitted)[0]
+            .1
+            .clone()
+            .collect()
+            .unwrap();
+        let column = |name: &str| {
+            let column = contributions.column(name).unwrap().f64().unwrap();
+            column.into_no_null_iter().collect::<Vec<_>>()
+        };
+
+        assert_eq!(column("x_contribution"), [0.25, 2.25]);
+        assert_eq!(column("sqdist"), [0.25, 2.25]);
+    }
+
+    #[test]
+    fn split_separates_an_outlier_from_duplicates() {
+        let mut x = vec![1.0; 7];
+        x.push(9.0);
+
+        for seed in 0..6 {
+            let before = result(
+                clusters(&[(0, vec![-5.0]), (1, x.clone())]),
+                vec![vec![-5.0], vec![2.0]],
+            );
+            let split = split_cluster(before, 1, seed).unwrap();
+
+            assert_eq!(split.n_clusters_found(), 3);
+            assert_eq!(split.requested_k, 2);
+            let mut centers = split.centers[1..].to_vec();
+            centers.sort_by(|a, b| a[0].total_cmp(&b[0]));
+            assert_eq!(centers, [vec![1.0], vec![9.0]]);
+        }
+    }
+
+    #[test]
+    fn every_index_is_labelled_exactly_once() {
+        let (df, _) = crate::make_blobs(120, 2, 3, 1.0, 6);
+        let result = KMeans::new(df, 3usize, None, false, CsvWriterOptions::default())
+            .unwrap()
+            .with_seed(1)
+            .fit();
+        let labels = into_labels(&result.clusters, "n");
+
+        assert_eq!(
+            labels.iter().map(|(n, _)| *n).collect::<Vec<_>>(),
+            (0..120).collect::<Vec<_>>()
+        );
+        assert!(labels.iter().all(|(_, cluster)| *cluster < 3));
+    }
+
+    #[test]
+    fn labels_follow_cluster_ids() {
+        let labels = into_labels(&clusters(&[(2, vec![0.0]), (1, vec![0.0, 0.0])]), "n");
+
+        assert_eq!(labels, [(100, 1), (101, 1), (200, 2)]);
+    }
+
+    #[test]
+    #[should_panic(expected = "n = 100 appears more than once")]
+    fn repeated_index_panics() {
+        let mut repeated = clusters(&[(1, vec![0.0])]);
+        repeated.push(repeated[0].clone());
+        into_labels(&repeated, "n");
+    }
+
+    #[test]
+    fn split_appends_after_the_largest_id() {
+        let before = result(
+            clusters(&[(0, vec![-5.0]), (5, vec![1.0, 1.0, 9.0])]),
+            vec![vec![-5.0], vec![11.0 / 3.0]],
+        );
+        let split = split_cluster(before, 5, 0).unwrap();
+
+        let ids: Vec<u64> = split.clusters.iter().map(|(id, _)| *id).collect();
+        assert_eq!(ids, [0, 5, 6]);
+    }
+
+    #[test]
+    fn split_rejects_unknown_ids_and_duplicates() {
+        let before = || {
+            result(
+                clusters(&[(0, vec![-5.0]), (1, vec![2.0, 2.0])]),
+                vec![vec![-5.0], vec![2.0]],
+            )
+        };
+
+        assert!(matches!(
+            split_cluster(before(), 7, 0),
+            Err(Error::UnknownCenterIds(ids)) if ids == [7]
+        ));
+        assert!(matches!(
+            split_cluster(before(), 1, 0),
+            Err(Error::TooFewDistinctPoints { k: 2, distinct: 1 })
+        ));
+    }
+}
@@ -0,0 +1,140 @@
+//! Must-link and cannot-link constraints for COP-KMeans style assignment.
+
+use std::collections::HashMap;
+
+use crate::Error;
+
+/// Pairwise constraints between rows, by `n`, see
+/// [`KMeans::with_constraints`](crate::KMeans::with_constraints).
+#[derive(Clone, Debug)]
+pub(crate) struct Constraints {
+    must_link: Vec<(u64, u64)>,
+    cannot_link: Vec<(u64, u64)>,
+}
+
+impl Constraints {
+    /// Fails with [`Error::UnsatisfiableConstraints`] when a cannot-link
+    /// pair is joined by a chain of must-links, or links a row to itself.
+    pub(crate) fn new(
+        must_link: Vec<(u64, u64)>,
+        cannot_link: Vec<(u64, u64)>,
+    ) -> Result<Self, Error> {
+        let mut groups = Groups::default();
+        for &(a, b) in &must_link {
+            groups.union(a, b);
+        }
+        if let Some(&(a, b)) = cannot_link
+            .iter()
+            .find(|(a, b)| groups.find(*a) == groups.find(*b))
+        {
+            return Err(Error::UnsatisfiableConstraints(a, b));
+        }
+
+        Ok(Constraints {
+            must_link,
+            cannot_link,
+        })
+    }
+
+    /// Every id named in a constraint.
+    pub(crate) fn ids(&self) -> impl Iterator<Item = u64> + '_ {
+        self.must_link
+            .iter()
+            .chain(&self.cannot_link)
+            .flat_map(|&(a, b)| [a, b])
+    }
+
+    /// Reassigns the constrained rows among `ids` (row order of
+    /// `distances`, one column per centroid) to their nearest feasible
+    /// centroid. Must-linked rows move together to the centroid closest to
+    /// them in total, one group at a time in row order, skipping centroids
+    /// already taken by a cannot-linked group. Unconstrained rows keep
+    /// their label. Returns the `n` of a row left without a feasible
+    /// centroid.
+    pub(crate) fn assign(
+        &self,
+        ids: &[u64],
+        distances: &[Vec<f64>],
+        labels: &mut [u64],
+    ) -> Result<(), u64> {
+        let position = ids
+            .iter()
+            .enumerate()
+            .map(|(i, n)| (*n, i))
+            .collect::<HashMap<_, _>>();
+        // Rows outside this fit, e.g. held out for validation, are skipped.
+        let present =
+            |&&(a, b): &&(u64, u64)| position.contains_key(&a) && position.contains_key(&b);
+
+        let mut groups = Groups::default();
+        for &(a, b) in self.must_link.iter().filter(present) {
+            groups.union(a, b);
+        }
+        let mut members = HashMap::<u64, Vec<usize>>::new();
+        for n in self.ids().filter(|n| position.contains_key(n)) {
+            let group = members.entry(groups.find(n)).or_default();
+            if !group.contains(&position[&n]) {
+                group.push(position[&n]);
+            }
+        }
+        let mut apart = HashMap::<u64, Vec<u64>>::new();
+        for &(a, b) in self.cannot_link.iter().filter(present) {
+            let (a, b) = (groups.find(a), groups.find(b));
+            apart.entry(a).or_default().push(b);
+            apart.entry(b).or_default().push(a);
+        }
+
+        let mut order = members.keys().copied().collect::<Vec<_>>();
+        order.sort_by_key(|group| members[group].iter().min().copied());
+
+        let mut assigned = HashMap::new();
+        for group in order {
+            let rows = &members[&group];
+            let mut centroids = (0..distances.len()).collect::<Vec<_>>();
+            let cost = |c: usize| rows.iter().map(|&i| distances[c][i]).sum::<f64>();
+            centroids.sort_by(|&a, &b| cost(a).total_cmp(&cost(b)));
+
+            let taken = apart
+                .get(&group)
+                .into_iter()
+                .flatten()
+                .filter_map(|other| assigned.get(other))
+                .collect::<Vec<_>>();
+            let Some(centroid) = centroids.into_iter().find(|c| !taken.contains(&c)) else {
+                return Err(ids[rows[0]]);
+            };
+
+            assigned.insert(group, centroid);
+            for &i in rows {
+                labels[i] = centroid as u64;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Union-find over row ids.
+#[derive(Default)]
+struct Groups {
+    parent: HashMap<u64, u64>,
+}
+
+impl Groups {
+    fn find(&mut self, n: u64) -> u64 {
+        let parent = *self.parent.entry(n).or_insert(n);
+        if parent == n {
+            return n;
+        }
+
+        let root = self.find(parent);
+        self.parent.insert(n, root);
+
+        root
+    }
+
+    fn union(&mut self, a: u64, b: u64) {
+        let (a, b) = (self.find(a), self.find(b));
+        self.parent.insert(a, b);
+    }
+}
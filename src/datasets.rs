@@ -0,0 +1,102 @@
+use polars::prelude::*;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+/// Generates `n_samples` points around `centers` random centroids drawn
+/// uniformly from `[-10, 10)` per feature, with isotropic Gaussian noise of
+/// standard deviation `cluster_std`. Samples are split evenly between
+/// centroids, the first ones taking the remainder.
+///
+/// Returns a frame with an `n` column and features `x0..x{n_features - 1}`,
+/// plus the true label of every row.
+pub fn make_blobs(
+    n_samples: usize,
+    n_features: usize,
+    centers: usize,
+    cluster_std: f64,
+    seed: u64,
+) -> (LazyFrame, Vec<u64>) {
+    assert!(centers > 0, "make_blobs needs at least one center");
+
+    let mut rng = StdRng::seed_from_u64(seed);
+    let center_points = (0..centers)
+        .map(|_| {
+            (0..n_features)
+                .map(|_| rng.gen_range(-10.0..10.0))
+                .collect::<Vec<f64>>()
+        })
+        .collect::<Vec<_>>();
+
+    let labels = (0..centers)
+        .flat_map(|c| {
+            let size = n_samples / centers + usize::from(c < n_samples % centers);
+            std::iter::repeat_n(c as u64, size)
+        })
+        .collect::<Vec<_>>();
+
+    let mut features = vec![Vec::with_capacity(n_samples); n_features];
+    for &label in &labels {
+        for (column, c) in features.iter_mut().zip(&center_points[label as usize]) {
+            column.push(c + cluster_std * standard_normal(&mut rng));
+        }
+    }
+
+    let mut columns = vec![Series::new("n", (0..n_samples as u64).collect::<Vec<_>>())];
+    columns.extend(
+        features
+            .into_iter()
+            .enumerate()
+            .map(|(i, values)| Series::new(&format!("x{i}"), values)),
+    );
+
+    (DataFrame::new(columns).unwrap().lazy(), labels)
+}
+
+/// Box-Muller transform.
+fn standard_normal(rng: &mut StdRng) -> f64 {
+    let u1: f64 = 1.0 - rng.gen::<f64>();
+    let u2: f64 = rng.gen();
+
+    (-2.0 * u1.ln()).sqrt() * (2.0 * std::f64::consts::PI * u2).cos()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn blobs_have_the_requested_shape() {
+        let (df, labels) = make_blobs(10, 3, 3, 0.5, 1);
+        let df = df.collect().unwrap();
+
+        assert_eq!(df.shape(), (10, 4));
+        assert_eq!(df.get_column_names(), ["n", "x0", "x1", "x2"]);
+        assert_eq!(labels, [0, 0, 0, 0, 1, 1, 1, 2, 2, 2]);
+    }
+
+    #[test]
+    fn blobs_are_reproducible() {
+        let (a, _) = make_blobs(50, 2, 4, 1.0, 7);
+        let (b, _) = make_blobs(50, 2, 4, 1.0, 7);
+        let (c, _) = make_blobs(50, 2, 4, 1.0, 8);
+        let (a, b, c) = (
+            a.collect().unwrap(),
+            b.collect().unwrap(),
+            c.collect().unwrap(),
+        );
+
+        assert!(a.frame_equal(&b));
+        assert!(!a.frame_equal(&c));
+    }
+
+    #[test]
+    fn zero_std_puts_every_sample_on_its_centroid() {
+        let (df, labels) = make_blobs(9, 2, 3, 0.0, 3);
+        let df = df.collect().unwrap();
+        let x0 = df.column("x0").unwrap().f64().unwrap();
+
+        for (i, label) in labels.iter().enumerate() {
+            let first = labels.iter().position(|l| l == label).unwrap();
+            assert_eq!(x0.get(i), x0.get(first));
+        }
+    }
+}
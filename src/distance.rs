@@ -0,0 +1,248 @@
+use std::{fmt, str::FromStr};
+
+#[cfg(feature = "full")]
+use polars::prelude::*;
+
+use crate::error::{from_name, Error};
+
+pub(crate) const KL_EPSILON: f64 = 1e-10;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum DistanceMetric {
+    #[default]
+    Euclidean,
+    /// `Σ x_i log(x_i / c_i)` for rows that are probability vectors. Zero
+    /// coordinates are guarded with a small epsilon; the centroid update
+    /// stays the mean, which is the right minimiser for this divergence.
+    KLDivergence,
+    /// Euclidean distance with each squared difference divided by the
+    /// squared range of its feature, so features in different units weigh
+    /// alike. Unlike [`Scaling`](crate::Scaling) this only happens inside
+    /// the distance: the data and centroids stay in their original units.
+    /// `KMeans` measures the ranges on the rows it fits; used on its own,
+    /// without ranges, this is plain Euclidean distance.
+    RangeNormalized,
+}
+
+impl DistanceMetric {
+    /// Every metric, e.g. to list them in a UI.
+    pub const fn all() -> &'static [DistanceMetric] {
+        &[
+            DistanceMetric::Euclidean,
+            DistanceMetric::KLDivergence,
+            DistanceMetric::RangeNormalized,
+        ]
+    }
+
+    /// Distance from every row of `features` to `center` as a Polars expression.
+    #[cfg(feature = "full")]
+    pub fn expr(&self, features: &[String], center: &[f64]) -> Expr {
+        let terms = features.iter().zip(center).map(|(f, c)| match self {
+            DistanceMetric::Euclidean | DistanceMetric::RangeNormalized => {
+                (col(f) - lit(*c)).pow(2)
+            }
+            DistanceMetric::KLDivergence => {
+                col(f) * ((col(f) + lit(KL_EPSILON)) / lit(c + KL_EPSILON)).log(std::f64::consts::E)
+            }
+        });
+        let sum = terms.reduce(|acc, e| acc + e).unwrap();
+
+        match self {
+            DistanceMetric::Euclidean | DistanceMetric::RangeNormalized => sum.sqrt(),
+            DistanceMetric::KLDivergence => sum,
+        }
+    }
+
+    /// [`expr`](Self::expr) for [`DistanceMetric::RangeNormalized`] with the
+    /// range of every feature.
+    #[cfg(feature = "full")]
+    pub(crate) fn range_normalized_expr(
+        features: &[String],
+        center: &[f64],
+        ranges: &[f64],
+    ) -> Expr {
+        features
+            .iter()
+            .zip(center)
+            .zip(ranges)
+            .map(|((f, c), r)| ((col(f) - lit(*c)) / lit(*r)).pow(2))
+            .reduce(|acc, e| acc + e)
+            .unwrap()
+            .sqrt()
+    }
+
+    pub fn distance(&self, a: &[f64], b: &[f64]) -> f64 {
+        match self {
+            DistanceMetric::Euclidean | DistanceMetric::RangeNormalized => {
+                squared_euclidean(a, b).sqrt()
+            }
+            DistanceMetric::KLDivergence => a
+                .iter()
+                .zip(b)
+                .map(|(x, c)| x * ((x + KL_EPSILON) / (c + KL_EPSILON)).ln())
+                .sum(),
+        }
+    }
+
+    /// Checks that `features` hold non-negative values, warning when rows are
+    /// not normalised to sum to one.
+    #[cfg(feature = "full")]
+    pub(crate) fn validate(&self, df: &LazyFrame, features: &[String]) {
+        if *self != DistanceMetric::KLDivergence {
+            return;
+        }
+
+        let row_sum = features
+            .iter()
+            .map(|f| col(f))
+            .reduce(|acc, e| acc + e)
+            .unwrap();
+
+        let mut exprs = features.iter().map(|f| col(f).min()).collect::<Vec<_>>();
+        exprs.push(row_sum.clone().min().alias("__min_sum"));
+        exprs.push(row_sum.max().alias("__max_sum"));
+
+        let stats = df.clone().select(exprs).collect().unwrap();
+        let stats = stats
+            .get(0)
+            .unwrap()
+            .into_iter()
+            .map(|v| v.try_extract::<f64>().unwrap())
+            .collect::<Vec<_>>();
+
+        let (min, sums) = stats.split_at(features.len());
+        let min = min.iter().copied().fold(f64::MAX, f64::min);
+        let deviation = (sums[0] - 1.0).abs().max((sums[1] - 1.0).abs());
+
+        assert!(
+            min >= 0.0,
+            "KL divergence requires non-negative features, found {min}"
+        );
+
+        if deviation > 1e-6 {
+            eprintln!("warning: rows do not sum to 1 (max deviation {deviation:.3e}), KL divergence assumes probability vectors");
+        }
+    }
+}
+
+impl fmt::Display for DistanceMetric {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            DistanceMetric::Euclidean => "euclidean",
+            DistanceMetric::KLDivergence => "kl-divergence",
+            DistanceMetric::RangeNormalized => "range-normalized",
+        })
+    }
+}
+
+/// Parses the [`Display`](fmt::Display) name, ignoring case.
+impl FromStr for DistanceMetric {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        from_name("distance metric", Self::all(), s)
+    }
+}
+
+/// Squared Euclidean distance between two points, vectorised four lanes at
+/// a time when the `simd` feature is enabled.
+pub fn squared_euclidean(a: &[f64], b: &[f64]) -> f64 {
+    #[cfg(feature = "simd")]
+    return squared_euclidean_simd(a, b);
+    #[cfg(not(feature = "simd"))]
+    squared_euclidean_scalar(a, b)
+}
+
+/// [`squared_euclidean`] one coordinate at a time, whatever the features.
+pub fn squared_euclidean_scalar(a: &[f64], b: &[f64]) -> f64 {
+    a.iter().zip(b).map(|(x, c)| (x - c).powi(2)).sum()
+}
+
+/// [`squared_euclidean`] four lanes at a time. Sums in a different order
+/// than [`squared_euclidean_scalar`], so results agree within rounding.
+#[cfg(feature = "simd")]
+pub fn squared_euclidean_simd(a: &[f64], b: &[f64]) -> f64 {
+    use wide::f64x4;
+
+    let len = a.len().min(b.len());
+    let chunks = len / 4 * 4;
+
+    let mut acc = f64x4::ZERO;
+    for i in (0..chunks).step_by(4) {
+        let x = f64x4::new([a[i], a[i + 1], a[i + 2], a[i + 3]]);
+        let c = f64x4::new([b[i], b[i + 1], b[i + 2], b[i + 3]]);
+        let d = x - c;
+        acc += d * d;
+    }
+
+    acc.reduce_add()
+        + a[chunks..len]
+            .iter()
+            .zip(&b[chunks..len])
+            .map(|(x, c)| (x - c).powi(2))
+            .sum::<f64>()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn kl_divergence_on_the_simplex() {
+        let p = [0.5, 0.5, 0.0];
+        let q = [0.25, 0.25, 0.5];
+        let kl = DistanceMetric::KLDivergence;
+
+        assert_eq!(kl.distance(&p, &p), 0.0);
+        assert!((kl.distance(&p, &q) - 2f64.ln()).abs() < 1e-9);
+        assert!(kl.distance(&q, &p) > kl.distance(&p, &q));
+    }
+
+    #[test]
+    #[cfg(feature = "full")]
+    fn kl_expr_matches_distance() {
+        let features = ["a".to_owned(), "b".to_owned()];
+        let df = df!("a" => [0.2, 0.9, 0.0], "b" => [0.8, 0.1, 1.0]).unwrap();
+        let center = [0.6, 0.4];
+        let metric = DistanceMetric::KLDivergence;
+
+        let out = df
+            .clone()
+            .lazy()
+            .select([metric.expr(&features, &center).alias("d")])
+            .collect()
+            .unwrap();
+        let a = df.column("a").unwrap().f64().unwrap();
+        let b = df.column("b").unwrap().f64().unwrap();
+        let d = out.column("d").unwrap().f64().unwrap();
+
+        for i in 0..df.height() {
+            let row = [a.get(i).unwrap(), b.get(i).unwrap()];
+            assert!((d.get(i).unwrap() - metric.distance(&row, &center)).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "full")]
+    #[should_panic(expected = "KL divergence requires non-negative features")]
+    fn kl_rejects_negative_features() {
+        let df = df!("a" => [0.5, -0.1], "b" => [0.5, 1.1]).unwrap();
+        DistanceMetric::KLDivergence.validate(&df.lazy(), &["a".to_owned(), "b".to_owned()]);
+    }
+
+    #[test]
+    #[cfg(feature = "simd")]
+    fn simd_matches_scalar() {
+        for len in [0, 1, 3, 4, 7, 128] {
+            let a = (0..len)
+                .map(|i| (i as f64 * 0.37).sin())
+                .collect::<Vec<_>>();
+            let b = (0..len)
+                .map(|i| (i as f64 * 1.91).cos())
+                .collect::<Vec<_>>();
+            let scalar = squared_euclidean_scalar(&a, &b);
+
+            assert!((squared_euclidean_simd(&a, &b) - scalar).abs() <= 1e-12 * scalar.max(1.0));
+        }
+    }
+}
@@ -0,0 +1,167 @@
+use std::fmt;
+use std::path::PathBuf;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    /// `center_ids` holds a different number of distinct ids than the
+    /// requested number of clusters.
+    CenterIdsLength { expected: usize, found: usize },
+    /// Some `center_ids` do not match any row's `n`.
+    UnknownCenterIds(Vec<u64>),
+    /// The input has no `n` index column and generating one is disabled.
+    MissingIndex,
+    /// A centers file's columns differ from the data's feature columns.
+    CenterColumns {
+        expected: Vec<String>,
+        found: Vec<String>,
+    },
+    /// A centers file has a different number of rows than clusters.
+    CentersLength { expected: usize, found: usize },
+    /// Type overrides name columns missing from the file's header.
+    UnknownColumns(Vec<String>),
+    /// Some constraint ids do not match any row's `n`.
+    UnknownConstraintIds(Vec<u64>),
+    /// A cannot-link pair whose rows must-link constraints force into the
+    /// same cluster.
+    UnsatisfiableConstraints(u64, u64),
+    /// Assignment found no centroid for row `row` that keeps its
+    /// cannot-link constraints among `n_clusters` clusters.
+    InfeasibleConstraints { row: u64, n_clusters: usize },
+    /// Step `step` would write more per-step files than the configured cap.
+    TooManyOutputFiles {
+        step: usize,
+        max_output_files: usize,
+    },
+    /// There are no points to cluster.
+    EmptyInput,
+    /// The row with index `n` is a NaN or missing distance from a centroid,
+    /// from a NaN or missing feature under [`NanPolicy::Panic`](crate::NanPolicy).
+    NanDistance { n: u64 },
+    /// Initial centroid `seed` has a NaN or missing coordinate, so no row
+    /// could be assigned to it.
+    NanSeed { seed: usize },
+    /// The data holds fewer distinct feature vectors than clusters.
+    TooFewDistinctPoints { k: usize, distinct: usize },
+    /// A feature column has a type that cannot serve as a coordinate, such
+    /// as text, a boolean or a date. `dtype` is the Polars type name.
+    NonNumericFeature { column: String, dtype: String },
+    /// An input file is missing, unreadable or not valid CSV. `message` is
+    /// the Polars error.
+    ReadInput { path: PathBuf, message: String },
+    /// An input glob pattern is malformed or matches no file.
+    NoInputFiles(String),
+    /// An input file's feature columns differ from the first file's.
+    InputColumns {
+        path: PathBuf,
+        expected: Vec<String>,
+        found: Vec<String>,
+    },
+    /// A string names none of the variants of an enum such as
+    /// [`DistanceMetric`](crate::DistanceMetric).
+    UnknownName {
+        kind: &'static str,
+        name: String,
+        expected: Vec<String>,
+    },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::CenterIdsLength { expected, found } => write!(
+                f,
+                "expected {expected} distinct center ids, one per cluster, found {found}"
+            ),
+            Error::UnknownCenterIds(ids) => {
+                write!(f, "center ids {ids:?} do not match any row of `n`")
+            }
+            Error::MissingIndex => write!(f, "input has no `n` index column"),
+            Error::CenterColumns { expected, found } => write!(
+                f,
+                "centers file has columns {found:?}, expected the feature columns {expected:?}"
+            ),
+            Error::CentersLength { expected, found } => write!(
+                f,
+                "expected {expected} rows in the centers file, one per cluster, found {found}"
+            ),
+            Error::UnknownColumns(columns) => {
+                write!(f, "columns {columns:?} are not in the input header")
+            }
+            Error::UnknownConstraintIds(ids) => {
+                write!(f, "constraint ids {ids:?} do not match any row of `n`")
+            }
+            Error::UnsatisfiableConstraints(a, b) => write!(
+                f,
+                "rows {a} and {b} cannot be linked apart: must-links join them"
+            ),
+            Error::TooManyOutputFiles {
+                step,
+                max_output_files,
+            } => write!(
+                f,
+                "step {step} would write more than {max_output_files} output files; raise the \
+                 cap with with_max_output_files or turn off per-step output"
+            ),
+            Error::EmptyInput => write!(f, "no points to cluster"),
+            Error::NanDistance { n } => write!(
+                f,
+                "NaN distance from row n = {n} to a centroid, see NanPolicy"
+            ),
+            Error::NanSeed { seed } => {
+                write!(f, "NaN or missing feature in seed {seed}, see NanPolicy")
+            }
+            Error::TooFewDistinctPoints { k, distinct } => {
+                write!(f, "only {distinct} distinct points for {k} clusters")
+            }
+            Error::InfeasibleConstraints { row, n_clusters } => write!(
+                f,
+                "no centroid left for row {row} that keeps its cannot-link constraints \
+                 with {n_clusters} clusters"
+            ),
+            Error::NonNumericFeature { column, dtype } => {
+                write!(f, "feature column `{column}` is {dtype}, not numeric")
+            }
+            Error::ReadInput { path, message } => {
+                write!(f, "cannot read {}: {message}", path.display())
+            }
+            Error::NoInputFiles(pattern) => write!(f, "no input file matches `{pattern}`"),
+            Error::InputColumns {
+                path,
+                expected,
+                found,
+            } => write!(
+                f,
+                "{} has feature columns {found:?}, expected {expected:?} as in the first file",
+                path.display()
+            ),
+            Error::UnknownName {
+                kind,
+                name,
+                expected,
+            } => write!(
+                f,
+                "unknown {kind} `{name}`, expected one of {}",
+                expected.join(", ")
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// The variant of `all` whose [`Display`](fmt::Display) name is `name`,
+/// ignoring ASCII case.
+pub(crate) fn from_name<T: Copy + fmt::Display>(
+    kind: &'static str,
+    all: &[T],
+    name: &str,
+) -> Result<T, Error> {
+    all.iter()
+        .copied()
+        .find(|variant| variant.to_string().eq_ignore_ascii_case(name))
+        .ok_or_else(|| Error::UnknownName {
+            kind,
+            name: name.to_owned(),
+            expected: all.iter().map(ToString::to_string).collect(),
+        })
+}
@@ -0,0 +1,101 @@
+use std::{fs::File, io::BufWriter, path::Path};
+
+use polars::prelude::*;
+use serde_json::{json, Value};
+
+use crate::FitResult;
+
+/// Writes the clustering as a GeoJSON `FeatureCollection` with one `Point`
+/// per row, taking longitude from `x_col` and latitude from `y_col`, and
+/// carrying its `n` and `cluster` as properties. With `include_centroids`
+/// each cluster's centroid is added as a further point marked
+/// `"centroid": true`.
+pub fn write_geojson(
+    result: &FitResult,
+    x_col: &str,
+    y_col: &str,
+    include_centroids: bool,
+    path: impl AsRef<Path>,
+) -> std::io::Result<()> {
+    let mut features = Vec::new();
+
+    for (id, lf) in &result.clusters {
+        let df = lf
+            .clone()
+            .select([
+                col("n"),
+                col(x_col).cast(DataType::Float64),
+                col(y_col).cast(DataType::Float64),
+            ])
+            .collect()
+            .unwrap();
+
+        let n = df.column("n").unwrap().u64().unwrap();
+        let x = df.column(x_col).unwrap().f64().unwrap();
+        let y = df.column(y_col).unwrap().f64().unwrap();
+
+        for ((n, x), y) in n.into_iter().zip(x).zip(y) {
+            features.push(point(x, y, json!({ "n": n, "cluster": id })));
+        }
+
+        if include_centroids {
+            features.push(point(
+                x.mean(),
+                y.mean(),
+                json!({ "cluster": id, "centroid": true }),
+            ));
+        }
+    }
+
+    let collection = json!({ "type": "FeatureCollection", "features": features });
+
+    serde_json::to_writer(BufWriter::new(File::create(path)?), &collection)?;
+
+    Ok(())
+}
+
+/// The clustering as an `ndarray` feature matrix, one row per point ordered
+/// by `n`, and the matching vector of cluster labels. Both come from one
+/// collected frame, so row `i` of the matrix is labelled `labels[i]`.
+#[cfg(feature = "ndarray")]
+pub fn to_ndarray(result: &FitResult) -> (ndarray::Array2<f64>, ndarray::Array1<u64>) {
+    let labelled = result
+        .clusters
+        .iter()
+        .map(|(id, lf)| lf.clone().with_column(lit(*id).alias("cluster")))
+        .collect::<Vec<_>>();
+    let df = concat(labelled, UnionArgs::default())
+        .unwrap()
+        .sort("n", SortOptions::default())
+        .collect()
+        .unwrap();
+
+    let labels = df
+        .column("cluster")
+        .unwrap()
+        .u64()
+        .unwrap()
+        .into_no_null_iter()
+        .collect();
+    let columns = df
+        .drop_many(&["n", "cluster"])
+        .iter()
+        .map(|s| {
+            let s = s.cast(&DataType::Float64).unwrap();
+            s.f64().unwrap().into_no_null_iter().collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+
+    let matrix =
+        ndarray::Array2::from_shape_fn((df.height(), columns.len()), |(i, j)| columns[j][i]);
+
+    (matrix, labels)
+}
+
+fn point(x: Option<f64>, y: Option<f64>, properties: Value) -> Value {
+    json!({
+        "type": "Feature",
+        "geometry": { "type": "Point", "coordinates": [x, y] },
+        "properties": properties,
+    })
+}
@@ -0,0 +1,192 @@
+//! Convex hull extent of point sets in two or three dimensions.
+
+/// Area of the convex hull of 2D points (Andrew's monotone chain). Fewer
+/// than three points or collinear points give 0.
+pub fn hull_area(points: &[Vec<f64>]) -> f64 {
+    let mut points = points.iter().map(|p| (p[0], p[1])).collect::<Vec<_>>();
+    points.sort_by(|a, b| a.0.total_cmp(&b.0).then(a.1.total_cmp(&b.1)));
+    points.dedup();
+    if points.len() < 3 {
+        return 0.0;
+    }
+
+    let cross = |o: (f64, f64), a: (f64, f64), b: (f64, f64)| {
+        (a.0 - o.0) * (b.1 - o.1) - (a.1 - o.1) * (b.0 - o.0)
+    };
+
+    let mut hull: Vec<(f64, f64)> = Vec::new();
+    for pass in [points.clone(), points.into_iter().rev().collect()] {
+        let start = hull.len();
+        for p in pass {
+            while hull.len() >= start + 2
+                && cross(hull[hull.len() - 2], hull[hull.len() - 1], p) <= 0.0
+            {
+                hull.pop();
+            }
+            hull.push(p);
+        }
+        hull.pop();
+    }
+
+    let twice_area = (0..hull.len())
+        .map(|i| {
+            let (a, b) = (hull[i], hull[(i + 1) % hull.len()]);
+            a.0 * b.1 - b.0 * a.1
+        })
+        .sum::<f64>();
+
+    twice_area.abs() / 2.0
+}
+
+/// Volume of the convex hull of 3D points, built incrementally from an
+/// initial tetrahedron. Fewer than four points or coplanar points give 0.
+pub fn hull_volume(points: &[Vec<f64>]) -> f64 {
+    let points = points
+        .iter()
+        .map(|p| [p[0], p[1], p[2]])
+        .collect::<Vec<_>>();
+    let Some(first) = points.first() else {
+        return 0.0;
+    };
+
+    let extent = points
+        .iter()
+        .flat_map(|p| (0..3).map(move |d| (p[d] - first[d]).abs()))
+        .fold(0.0, f64::max);
+    let eps = 1e-9 * extent.max(f64::MIN_POSITIVE);
+
+    // Initial tetrahedron: the farthest point from the first, the farthest
+    // from that line, then the farthest from that plane.
+    let farthest = |score: &dyn Fn(&[f64; 3]) -> f64| {
+        (0..points.len())
+            .max_by(|a, b| score(&points[*a]).total_cmp(&score(&points[*b])))
+            .unwrap()
+    };
+    let p0 = 0;
+    let p1 = farthest(&|p| norm(sub(*p, points[p0])));
+    let p2 = farthest(&|p| {
+        norm(cross(sub(points[p1], points[p0]), sub(*p, points[p0])))
+            / norm(sub(points[p1], points[p0]))
+    });
+    let base = unit(cross(
+        sub(points[p1], points[p0]),
+        sub(points[p2], points[p0]),
+    ));
+    let p3 = farthest(&|p| dot(base, sub(*p, points[p0])).abs());
+
+    if norm(sub(points[p1], points[p0])) <= eps
+        || dot(base, sub(points[p3], points[p0])).abs() <= eps
+    {
+        return 0.0;
+    }
+
+    let interior =
+        [0, 1, 2].map(|d| (points[p0][d] + points[p1][d] + points[p2][d] + points[p3][d]) / 4.0);
+
+    // Faces are oriented so that their normal points away from `interior`.
+    let orient = |[a, b, c]: [usize; 3]| {
+        let normal = cross(sub(points[b], points[a]), sub(points[c], points[a]));
+        if dot(normal, sub(interior, points[a])) > 0.0 {
+            [a, c, b]
+        } else {
+            [a, b, c]
+        }
+    };
+    let mut faces = vec![
+        orient([p0, p1, p2]),
+        orient([p0, p1, p3]),
+        orient([p0, p2, p3]),
+        orient([p1, p2, p3]),
+    ];
+
+    for (i, p) in points.iter().enumerate() {
+        if [p0, p1, p2, p3].contains(&i) {
+            continue;
+        }
+
+        let visible = faces
+            .iter()
+            .map(|&[a, b, c]| {
+                let normal = unit(cross(sub(points[b], points[a]), sub(points[c], points[a])));
+                dot(normal, sub(*p, points[a])) > eps
+            })
+            .collect::<Vec<_>>();
+        if !visible.contains(&true) {
+            continue;
+        }
+
+        // Horizon: edges of visible faces whose reverse edge belongs to a
+        // hidden face.
+        let visible_edges = faces
+            .iter()
+            .zip(&visible)
+            .filter(|(_, v)| **v)
+            .flat_map(|(&[a, b, c], _)| [(a, b), (b, c), (c, a)])
+            .collect::<Vec<_>>();
+        let horizon = visible_edges
+            .iter()
+            .filter(|(a, b)| !visible_edges.contains(&(*b, *a)))
+            .copied()
+            .collect::<Vec<_>>();
+
+        faces = faces
+            .into_iter()
+            .zip(visible)
+            .filter(|(_, v)| !v)
+            .map(|(face, _)| face)
+            .collect();
+        faces.extend(horizon.into_iter().map(|(a, b)| [a, b, i]));
+    }
+
+    faces
+        .iter()
+        .map(|&[a, b, c]| {
+            dot(
+                sub(points[a], interior),
+                cross(sub(points[b], interior), sub(points[c], interior)),
+            )
+            .abs()
+                / 6.0
+        })
+        .sum()
+}
+
+/// Hull area for 2D points, hull volume for 3D points.
+///
+/// Panics for any other dimensionality.
+pub fn hull_measure(points: &[Vec<f64>]) -> f64 {
+    match points.first().map_or(2, Vec::len) {
+        2 => hull_area(points),
+        3 => hull_volume(points),
+        dims => panic!("convex hulls are only supported in 2 or 3 dimensions, got {dims}"),
+    }
+}
+
+fn sub(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn dot(a: [f64; 3], b: [f64; 3]) -> f64 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn cross(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+fn norm(a: [f64; 3]) -> f64 {
+    dot(a, a).sqrt()
+}
+
+fn unit(a: [f64; 3]) -> [f64; 3] {
+    let n = norm(a);
+    if n > 0.0 {
+        [a[0] / n, a[1] / n, a[2] / n]
+    } else {
+        a
+    }
+}
@@ -0,0 +1,248 @@
+use std::{fmt, str::FromStr};
+
+use rand::Rng;
+
+use crate::{
+    error::{from_name, Error},
+    DistanceMetric,
+};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum InitMethod {
+    /// Seeds drawn uniformly at random from the rows.
+    #[default]
+    Random,
+    /// k-means++ seeding: each further seed is drawn with probability
+    /// proportional to `D^power`, `D` being the distance to the nearest seed
+    /// chosen so far. A power of 2.0 is standard k-means++, 0.0 reduces to
+    /// uniform sampling and large powers approach farthest-point selection.
+    KMeansPlusPlus { power: f64 },
+    /// Deterministic seeds from per-feature quantiles: seed `j` of `k` takes,
+    /// for every feature independently, the `(j + 0.5) / k` quantile with
+    /// linear interpolation. Seeds are spread along the diagonal of the
+    /// data's quantile space and need no random numbers.
+    Quantile,
+}
+
+impl InitMethod {
+    /// Standard k-means++, weighting candidates by squared distance.
+    pub fn kmeans_plus_plus() -> Self {
+        InitMethod::KMeansPlusPlus { power: 2.0 }
+    }
+
+    /// Every method, k-means++ with its standard power.
+    pub const fn all() -> &'static [InitMethod] {
+        &[
+            InitMethod::Random,
+            InitMethod::KMeansPlusPlus { power: 2.0 },
+            InitMethod::Quantile,
+        ]
+    }
+}
+
+/// `random`, `quantile`, and `kmeans++` for the standard power or
+/// `kmeans++:<power>` for any other.
+impl fmt::Display for InitMethod {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InitMethod::Random => f.write_str("random"),
+            InitMethod::KMeansPlusPlus { power } if *power == 2.0 => f.write_str("kmeans++"),
+            InitMethod::KMeansPlusPlus { power } => write!(f, "kmeans++:{power}"),
+            InitMethod::Quantile => f.write_str("quantile"),
+        }
+    }
+}
+
+/// Parses the [`Display`](fmt::Display) name, ignoring case.
+impl FromStr for InitMethod {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let power = s
+            .split_once(':')
+            .filter(|(name, _)| name.eq_ignore_ascii_case("kmeans++"))
+            .and_then(|(_, power)| power.parse::<f64>().ok());
+
+        match power {
+            Some(power) => Ok(InitMethod::KMeansPlusPlus { power }),
+            None => from_name("init method", Self::all(), s),
+        }
+    }
+}
+
+/// Quantile levels used by [`InitMethod::Quantile`] for `n_clusters` seeds.
+pub(crate) fn quantile_levels(n_clusters: usize) -> Vec<f64> {
+    (0..n_clusters)
+        .map(|j| (j as f64 + 0.5) / n_clusters as f64)
+        .collect()
+}
+
+/// Seeds for [`InitMethod::Quantile`], computed in memory.
+pub(crate) fn quantile_centers(points: &[Vec<f64>], n_clusters: usize) -> Vec<Vec<f64>> {
+    let columns = (0..points[0].len())
+        .map(|j| {
+            let mut column = points.iter().map(|p| p[j]).collect::<Vec<_>>();
+            column.sort_by(f64::total_cmp);
+            column
+        })
+        .collect::<Vec<_>>();
+
+    quantile_levels(n_clusters)
+        .into_iter()
+        .map(|q| columns.iter().map(|c| quantile(c, q)).collect())
+        .collect()
+}
+
+/// Linearly interpolated quantile of sorted values, matching Polars'
+/// `QuantileInterpolOptions::Linear`.
+fn quantile(sorted: &[f64], q: f64) -> f64 {
+    let float_idx = (sorted.len() - 1) as f64 * q;
+    let idx = float_idx as usize;
+    let top = float_idx.ceil() as usize;
+
+    if idx == top {
+        sorted[idx]
+    } else {
+        sorted[idx] + (float_idx - idx as f64) * (sorted[top] - sorted[idx])
+    }
+}
+
+/// Draws per seed that [`kmeans_plus_plus`] spends looking for a candidate
+/// at least `min_separation` from the chosen seeds.
+const MAX_SEED_RESAMPLES: usize = 32;
+
+/// Returns the indices of `n_clusters` distinct seeds among `points`.
+/// Candidates closer than `min_separation` to a chosen seed are redrawn, up
+/// to [`MAX_SEED_RESAMPLES`] times, after which the farthest draw is kept.
+pub(crate) fn kmeans_plus_plus(
+    points: &[Vec<f64>],
+    n_clusters: usize,
+    power: f64,
+    min_separation: f64,
+    metric: DistanceMetric,
+    rng: &mut impl Rng,
+) -> Vec<usize> {
+    let n_clusters = n_clusters.min(points.len());
+    if n_clusters == 0 {
+        return Vec::new();
+    }
+
+    let mut seeds = vec![rng.gen_range(0..points.len())];
+    let mut nearest = points
+        .iter()
+        .map(|p| metric.distance(p, &points[seeds[0]]))
+        .collect::<Vec<_>>();
+
+    while seeds.len() < n_clusters {
+        // Distances are scaled to [0, 1] so large powers cannot overflow.
+        let max = nearest.iter().copied().fold(0.0, f64::max);
+        let weights = nearest
+            .iter()
+            .enumerate()
+            .map(|(i, d)| {
+                if seeds.contains(&i) {
+                    0.0
+                } else if max > 0.0 {
+                    (d / max).powf(power)
+                } else {
+                    1.0
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let total = weights.iter().sum::<f64>();
+        let mut draw = || {
+            if total > 0.0 {
+                let mut target = rng.gen_range(0.0..total);
+                weights
+                    .iter()
+                    .position(|w| {
+                        target -= w;
+                        target < 0.0
+                    })
+                    .unwrap_or_else(|| weights.iter().rposition(|w| *w > 0.0).unwrap())
+            } else {
+                // Every remaining point coincides with a seed: pick uniformly.
+                let rest = (0..points.len())
+                    .filter(|i| !seeds.contains(i))
+                    .collect::<Vec<_>>();
+                rest[rng.gen_range(0..rest.len())]
+            }
+        };
+
+        let mut next = draw();
+        for _ in 0..MAX_SEED_RESAMPLES {
+            if nearest[next] >= min_separation {
+                break;
+            }
+
+            let candidate = draw();
+            if nearest[candidate] > nearest[next] {
+                next = candidate;
+            }
+        }
+
+        seeds.push(next);
+        for (d, p) in nearest.iter_mut().zip(points) {
+            *d = d.min(metric.distance(p, &points[next]));
+        }
+    }
+
+    seeds
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+
+    /// Share of `trials` two-seed draws that pick the outlier of four points.
+    fn outlier_share(power: f64, trials: usize) -> f64 {
+        let points = vec![vec![0.0], vec![0.1], vec![0.2], vec![100.0]];
+        let mut rng = StdRng::seed_from_u64(1);
+        let hits = (0..trials)
+            .filter(|_| {
+                kmeans_plus_plus(&points, 2, power, 0.0, DistanceMetric::Euclidean, &mut rng)
+                    .contains(&3)
+            })
+            .count();
+
+        hits as f64 / trials as f64
+    }
+
+    #[test]
+    fn zero_power_samples_uniformly() {
+        // Uniform picks the outlier first with probability 1/4, else second
+        // with 1/3: 1/2 overall.
+        assert!((outlier_share(0.0, 4000) - 0.5).abs() < 0.03);
+    }
+
+    #[test]
+    fn squared_distance_favours_far_points() {
+        assert!(outlier_share(2.0, 1000) > 0.99);
+    }
+
+    #[test]
+    fn quantile_seeds_take_per_feature_quantiles() {
+        let points = (0..5)
+            .map(|i| vec![i as f64, 10.0 - 2.0 * i as f64])
+            .collect::<Vec<_>>();
+
+        assert_eq!(quantile_levels(2), [0.25, 0.75]);
+        assert_eq!(
+            quantile_centers(&points, 2),
+            [vec![1.0, 4.0], vec![3.0, 8.0]]
+        );
+    }
+
+    #[test]
+    fn seeds_are_distinct() {
+        let points = vec![vec![1.0]; 5];
+        let mut rng = StdRng::seed_from_u64(2);
+        let mut seeds = kmeans_plus_plus(&points, 5, 2.0, 0.0, DistanceMetric::Euclidean, &mut rng);
+        seeds.sort_unstable();
+
+        assert_eq!(seeds, [0, 1, 2, 3, 4]);
+    }
+}
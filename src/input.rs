@@ -0,0 +1,321 @@
+use std::path::{Path, PathBuf};
+
+use polars::prelude::*;
+
+use crate::Error;
+
+/// CSV source for clustering: an `n` index column plus numeric features,
+/// cast to `Float64` unless kept integer with
+/// [`with_integer_columns`](Self::with_integer_columns). Paths ending in
+/// `.gz` are read as gzipped CSV.
+///
+/// Plain files are scanned lazily, but a gzipped file is decompressed and
+/// parsed in full when it is loaded: both the decompressed text and the
+/// parsed frame are held in memory at once, and
+/// [`with_filter`](Self::with_filter) only applies afterwards. Decompress
+/// files that do not fit in memory before reading them.
+#[derive(Clone, Debug)]
+pub struct CsvInput {
+    paths: Vec<PathBuf>,
+    auto_index: bool,
+    integer_columns: Vec<String>,
+    index_as_feature: bool,
+    dtypes: Vec<(String, DataType)>,
+    filter: Option<Expr>,
+}
+
+impl CsvInput {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self::from_paths([path])
+    }
+
+    /// One dataset from several files with the same feature columns,
+    /// concatenated lazily in the given order. `n` is regenerated as the row
+    /// number across all files, replacing any `n` column of the files
+    /// themselves (kept as `n_feature` with
+    /// [`with_index_as_feature`](Self::with_index_as_feature)).
+    pub fn from_paths<P: Into<PathBuf>>(paths: impl IntoIterator<Item = P>) -> Self {
+        let paths = paths.into_iter().map(Into::into).collect::<Vec<_>>();
+        assert!(!paths.is_empty(), "CsvInput needs at least one path");
+
+        CsvInput {
+            paths,
+            auto_index: true,
+            integer_columns: Vec::new(),
+            index_as_feature: false,
+            dtypes: Vec::new(),
+            filter: None,
+        }
+    }
+
+    /// When the file has no `n` column one is generated from the row
+    /// number. Enabled by default; disable it to require a real index.
+    pub fn with_auto_index(mut self, auto_index: bool) -> Self {
+        self.auto_index = auto_index;
+
+        self
+    }
+
+    /// Keeps these feature columns in their inferred integer type instead of
+    /// casting them to `Float64`, so large counts stay exact. Distances and
+    /// centroids are still computed in floating point. Listed columns that
+    /// were not inferred as integers are cast as usual, with a warning.
+    pub fn with_integer_columns<S: AsRef<str>>(mut self, columns: &[S]) -> Self {
+        self.integer_columns = columns.iter().map(|c| c.as_ref().to_owned()).collect();
+
+        self
+    }
+
+    /// Clusters on the file's `n` column too: it is kept as the feature
+    /// `n_feature` and a fresh row-number `n` becomes the join key, so `n`
+    /// need not be unique. Off by default.
+    pub fn with_index_as_feature(mut self, index_as_feature: bool) -> Self {
+        self.index_as_feature = index_as_feature;
+
+        self
+    }
+
+    /// Parses these columns as the given types instead of inferring them,
+    /// e.g. a feature that a stray value would otherwise turn into text.
+    /// Every name must be in the header, or loading fails with
+    /// [`Error::UnknownColumns`].
+    pub fn with_dtypes<S: AsRef<str>>(mut self, dtypes: &[(S, DataType)]) -> Self {
+        self.dtypes = dtypes
+            .iter()
+            .map(|(c, dtype)| (c.as_ref().to_owned(), dtype.clone()))
+            .collect();
+
+        self
+    }
+
+    /// [`from_paths`](Self::from_paths) over the files matching a glob
+    /// pattern such as `parts/*.csv`, in path order.
+    pub fn from_glob(pattern: &str) -> Result<Self, Error> {
+        let paths = glob::glob(pattern)
+            .map_err(|_| Error::NoInputFiles(pattern.to_owned()))?
+            .filter_map(Result::ok)
+            .collect::<Vec<_>>();
+        if paths.is_empty() {
+            return Err(Error::NoInputFiles(pattern.to_owned()));
+        }
+
+        Ok(Self::from_paths(paths))
+    }
+
+    /// Keeps only the rows matching `predicate`, which may use `n` and the
+    /// features as loaded (floats unless kept integer). The filter runs
+    /// after `n` is assigned, so a generated `n` still numbers the rows of
+    /// the whole input and ids stay stable references to the original rows:
+    /// `center_ids`, warm starts and joins against the unfiltered data keep
+    /// working, but `center_ids` must name rows that pass the filter.
+    pub fn with_filter(mut self, predicate: Expr) -> Self {
+        self.filter = Some(predicate);
+
+        self
+    }
+
+    pub fn load(&self) -> Result<LazyFrame, Error> {
+        let df = match self.paths.as_slice() {
+            [path] => self.load_file(path, false)?,
+            _ => self.concat_files()?,
+        };
+
+        Ok(match &self.filter {
+            Some(predicate) => df.filter(predicate.clone()),
+            None => df,
+        })
+    }
+
+    /// Every file of a multi-file input as one frame, numbered across files.
+    fn concat_files(&self) -> Result<LazyFrame, Error> {
+        let frames = self
+            .paths
+            .iter()
+            .map(|path| self.load_file(path, true))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let columns = |lf: &LazyFrame| {
+            lf.schema()
+                .unwrap()
+                .iter_names()
+                .map(|c| c.to_string())
+                .collect::<Vec<_>>()
+        };
+        let expected = columns(&frames[0]);
+        let mut sorted = expected.clone();
+        sorted.sort();
+
+        let mut aligned = Vec::with_capacity(frames.len());
+        for (path, lf) in self.paths.iter().zip(frames) {
+            let found = columns(&lf);
+            let mut found_sorted = found.clone();
+            found_sorted.sort();
+            if found_sorted != sorted {
+                return Err(Error::InputColumns {
+                    path: path.clone(),
+                    expected,
+                    found,
+                });
+            }
+
+            aligned.push(lf.select(expected.iter().map(|c| col(c)).collect::<Vec<_>>()));
+        }
+
+        let df = concat(
+            aligned,
+            UnionArgs {
+                to_supertypes: true,
+                ..Default::default()
+            },
+        )
+        .unwrap()
+        .with_row_count("n", None);
+
+        let mut exprs = vec![col("n").cast(DataType::UInt64)];
+        exprs.extend(expected.iter().map(|c| col(c)));
+
+        Ok(df.select(exprs))
+    }
+
+    /// Loads one file. With `regenerate_index` the file's own `n` is
+    /// dropped and left for [`load`](Self::load) to number across files.
+    fn load_file(&self, path: &Path, regenerate_index: bool) -> Result<LazyFrame, Error> {
+        // The lazy scanner cannot read compressed files, so gzipped input is
+        // decompressed and parsed eagerly instead, in memory (see the type's
+        // docs).
+        let overrides = (!self.dtypes.is_empty()).then(|| {
+            self.dtypes
+                .iter()
+                .map(|(c, dtype)| Field::new(c, dtype.clone()))
+                .collect::<Schema>()
+        });
+        let read_error = |e: PolarsError| Error::ReadInput {
+            path: path.to_path_buf(),
+            message: e.to_string(),
+        };
+        let mut df = if path.extension().is_some_and(|ext| ext == "gz") {
+            CsvReader::from_path(path)
+                .map_err(read_error)?
+                .has_header(true)
+                .with_dtypes(overrides.clone().map(Arc::new))
+                .finish()
+                .map_err(read_error)?
+                .lazy()
+        } else {
+            LazyCsvReader::new(path)
+                .has_header(true)
+                .with_dtype_overwrite(overrides.as_ref())
+                .finish()
+                .map_err(read_error)?
+        };
+
+        // Polars silently ignores overrides for columns it does not find.
+        let header = df.schema().unwrap();
+        let unknown = self
+            .dtypes
+            .iter()
+            .filter(|(c, _)| header.get(c).is_none())
+            .map(|(c, _)| c.clone())
+            .collect::<Vec<_>>();
+        if !unknown.is_empty() {
+            return Err(Error::UnknownColumns(unknown));
+        }
+
+        if self.index_as_feature && df.schema().unwrap().get("n").is_some() {
+            df = df.rename(["n"], ["n_feature"]);
+        }
+
+        let schema = df.schema().unwrap();
+        let kept = self
+            .integer_columns
+            .iter()
+            .filter(|c| match schema.get(c) {
+                Some(dtype) if dtype.is_integer() => true,
+                Some(dtype) => {
+                    eprintln!(
+                        "warning: column {c} is {dtype}, not an integer type; casting to f64"
+                    );
+                    false
+                }
+                None => {
+                    eprintln!("warning: integer column {c} not found in input");
+                    false
+                }
+            })
+            .map(String::as_str)
+            .collect::<Vec<_>>();
+
+        let mut exprs = Vec::new();
+        if !regenerate_index {
+            if schema.get("n").is_none() {
+                if !self.auto_index && !self.index_as_feature {
+                    return Err(Error::MissingIndex);
+                }
+
+                df = df.with_row_count("n", None);
+            }
+            exprs.push(col("n").cast(DataType::UInt64));
+        }
+
+        // Selecting column by column keeps the input order for mixed
+        // integer and float schemas.
+        exprs.extend(schema.iter_names().filter(|c| c.as_str() != "n").map(|c| {
+            if kept.contains(&c.as_str()) {
+                col(c)
+            } else {
+                col(c).cast(DataType::Float64)
+            }
+        }));
+
+        Ok(df.select(exprs))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{into_labels, KMeans};
+
+    #[test]
+    fn missing_files_are_an_error() {
+        for name in ["kmeans_input_missing.csv", "kmeans_input_missing.csv.gz"] {
+            let path = std::env::temp_dir().join(name);
+            let result = CsvInput::new(&path).load();
+
+            assert!(matches!(result.err(), Some(Error::ReadInput { path: p, .. }) if p == path));
+        }
+    }
+
+    #[test]
+    fn unreadable_gz_file_is_an_error() {
+        let path = std::env::temp_dir().join(format!("kmeans_input_{}.csv.gz", std::process::id()));
+        std::fs::create_dir_all(&path).unwrap();
+        let result = CsvInput::new(&path).load();
+        std::fs::remove_dir(&path).unwrap();
+
+        assert!(matches!(result.err(), Some(Error::ReadInput { .. })));
+    }
+
+    #[test]
+    fn filtered_rows_keep_their_original_ids() {
+        let path = std::env::temp_dir().join(format!("kmeans_filter_{}.csv", std::process::id()));
+        std::fs::write(&path, "x\n0.0\n500.0\n1.0\n10.0\n600.0\n11.0\n").unwrap();
+        let df = CsvInput::new(&path)
+            .with_filter(col("x").lt(lit(100.0)))
+            .load()
+            .unwrap();
+
+        // Rows 2 and 5 are the third and sixth of the file, not of the
+        // filtered frame.
+        let result = KMeans::new(df, 2usize, Some(vec![2, 5]), false, Default::default())
+            .unwrap()
+            .fit();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(result.centers, vec![vec![0.5], vec![10.5]]);
+        assert_eq!(
+            into_labels(&result.clusters, "n"),
+            [(0, 0), (2, 0), (3, 1), (5, 1)]
+        );
+    }
+}
@@ -0,0 +1,61 @@
+//! Intrinsic dimensionality of a dataset, as a check before clustering.
+
+use polars::prelude::*;
+use rand::{rngs::StdRng, seq::index::sample, SeedableRng};
+use rayon::prelude::*;
+
+use crate::{metrics::points, squared_euclidean};
+
+/// TwoNN estimate (Facco et al., 2017) of the dimension of the manifold the
+/// rows of `df` lie on: the maximum-likelihood fit of `d` to the ratios
+/// `r2 / r1` of each point's second to first nearest-neighbour distance.
+/// Neighbours are searched within a seeded sample of at most `sample_size`
+/// rows, which is quadratic in the sample. Points with a duplicate are
+/// skipped.
+///
+/// A value well below the feature count means the data is low-rank and
+/// [`KMeans::with_pca`](crate::KMeans::with_pca) should help; a value
+/// close to a high feature count means distances concentrate and clusters
+/// found in the raw space are unreliable.
+///
+/// Panics if fewer than three sampled points have distinct neighbours.
+pub fn estimate_intrinsic_dim(df: LazyFrame, sample_size: usize, seed: u64) -> f64 {
+    let mut rows = points(&df);
+    if rows.len() > sample_size {
+        let mut rng = StdRng::seed_from_u64(seed);
+        rows = sample(&mut rng, rows.len(), sample_size)
+            .into_iter()
+            .map(|i| rows[i].clone())
+            .collect();
+    }
+
+    let log_ratios = rows
+        .par_iter()
+        .enumerate()
+        .filter_map(|(i, p)| {
+            let mut r1 = f64::MAX;
+            let mut r2 = f64::MAX;
+            for (j, q) in rows.iter().enumerate() {
+                if i == j {
+                    continue;
+                }
+                let d = squared_euclidean(p, q);
+                if d < r1 {
+                    r2 = r1;
+                    r1 = d;
+                } else if d < r2 {
+                    r2 = d;
+                }
+            }
+
+            // Squared distances, hence the halved logarithm.
+            (r1 > 0.0 && r2 < f64::MAX).then(|| 0.5 * (r2 / r1).ln())
+        })
+        .collect::<Vec<_>>();
+    assert!(
+        log_ratios.len() >= 3,
+        "estimate_intrinsic_dim needs at least three points with distinct neighbours"
+    );
+
+    log_ratios.len() as f64 / log_ratios.iter().sum::<f64>()
+}
@@ -0,0 +1,3356 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    fmt,
+    fs::File,
+    io::BufWriter,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use polars::prelude::*;
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+use rayon::prelude::*;
+use serde_json::json;
+
+use crate::{
+    analysis::into_labels,
+    constraints::Constraints,
+    init::{kmeans_plus_plus, quantile_levels},
+    metrics::{adjusted_rand_index, points},
+    native::{self, Algorithm, CoincidentPolicy, Elkan, TieBreak},
+    sparse::SparseRows,
+    squared_euclidean, CsvInput, DistanceMetric, Error, InitMethod, MetricsBundle, OutputNames,
+    Pca, Scaler, Scaling, SelectionMetric, RESULT_DIRECTORY,
+};
+
+/// Outcome of a fit: the cluster frames keyed by id and the centroid of
+/// each, in the same order.
+#[derive(Clone)]
+pub struct FitResult {
+    pub clusters: Vec<(u64, LazyFrame)>,
+    pub centers: Vec<Vec<f64>>,
+    /// Population standard deviation of every feature within each cluster,
+    /// in `centers` order, measured around the cluster mean on the rows the
+    /// centroids were fitted on. Single-point clusters have 0. Together with
+    /// `centers` this describes each cluster as an axis-aligned Gaussian.
+    pub std_devs: Vec<Vec<f64>>,
+    /// Mean pairwise adjusted Rand index between the `n_init` restarts, or
+    /// `None` for a single run. Low values mean the partition depends on
+    /// the initialisation.
+    pub stability: Option<f64>,
+    /// Number of clusters asked for, also when [`DistinctPolicy::Reduce`]
+    /// lowered it. Empty clusters are dropped, so fewer may be found.
+    pub requested_k: usize,
+    /// Held-out rows partitioned by their nearest centroid when fitted with
+    /// [`KMeans::with_validation_frac`]. Ids match `clusters`; a cluster no
+    /// validation row falls into is missing.
+    pub validation: Option<Vec<(u64, LazyFrame)>>,
+    /// Score under the metric set with [`KMeans::with_selection`], computed
+    /// at the end of the fit on the validation rows if there are any and
+    /// on the clusters otherwise. `None` without a selection metric.
+    pub selection_score: Option<f64>,
+    /// Size of every cluster after each assignment step, see
+    /// [`KMeans::size_history`].
+    pub size_history: Vec<Vec<usize>>,
+}
+
+impl FitResult {
+    pub fn n_clusters_requested(&self) -> usize {
+        self.requested_k
+    }
+
+    /// Number of non-empty clusters in the result.
+    pub fn n_clusters_found(&self) -> usize {
+        self.clusters.len()
+    }
+
+    /// Centroids as one flat row-major array, like scikit-learn's
+    /// `cluster_centers_`: all coordinates of centroid 0 in feature order,
+    /// then centroid 1, and so on. Returned with its `(k, n_features)`
+    /// shape.
+    pub fn centers_flat(&self) -> (Vec<f64>, usize, usize) {
+        let n_features = self.centers.first().map_or(0, Vec::len);
+
+        (self.centers.concat(), self.centers.len(), n_features)
+    }
+
+    /// Centroids for a warm-started fit with one more cluster: every current
+    /// centroid plus the row of the largest cluster farthest from its own
+    /// centroid, which splits that cluster off.
+    pub fn split_largest(&self) -> Vec<Vec<f64>> {
+        let mut centers = self.centers.clone();
+
+        let largest = self
+            .clusters
+            .iter()
+            .zip(&self.centers)
+            .map(|((_, lf), center)| (points(lf), center))
+            .max_by_key(|(points, _)| points.len());
+
+        if let Some((points, center)) = largest {
+            let farthest = points.into_iter().max_by(|a, b| {
+                squared_euclidean(a, center).total_cmp(&squared_euclidean(b, center))
+            });
+            centers.extend(farthest);
+        }
+
+        centers
+    }
+}
+
+/// What a [`KMeans::with_convergence`] predicate sees after each Lloyd
+/// step, once the centroids have been updated.
+#[derive(Clone, Copy, Debug)]
+pub struct ConvergenceState<'a> {
+    /// 1-based number of the step just completed.
+    pub step: usize,
+    /// Centroids the rows were assigned to in this step.
+    pub previous_centers: &'a [Vec<f64>],
+    /// Centroids updated from this step's assignment. Fewer than
+    /// `previous_centers` when a cluster emptied or was merged away.
+    pub centers: &'a [Vec<f64>],
+    /// Rows whose label differs from the previous step; every row on the
+    /// first step. Labels are compared as raw ids, so a renumbering counts
+    /// as a change.
+    pub labels_changed: usize,
+    /// Sum over rows of the squared distance to the nearest updated
+    /// centroid, or of the divergence itself under
+    /// [`DistanceMetric::KLDivergence`].
+    pub inertia: f64,
+}
+
+/// Overlapping assignment: a point belongs to every cluster whose centroid
+/// lies within `radius` under the fitted metric.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RadiusAssignment {
+    pub radius: f64,
+}
+
+/// Largest feature count for which [`Algorithm::Auto`] picks Elkan, whose
+/// bounds prune less as dimensions grow.
+const AUTO_ELKAN_MAX_DIMS: usize = 32;
+
+/// Largest number of `n × k` lower bounds [`Algorithm::Auto`] lets Elkan
+/// keep in memory.
+const AUTO_ELKAN_MAX_BOUNDS: usize = 25_000_000;
+
+/// Default cap on the per-step files one `eval` may write, see
+/// [`KMeans::with_max_output_files`].
+const DEFAULT_MAX_OUTPUT_FILES: usize = 10_000;
+
+/// Steps after which [`KMeans::fit_lazy`] stops without converging.
+const FIT_LAZY_MAX_STEPS: usize = 300;
+
+/// Relative movement, per coordinate, below which [`KMeans::fit_lazy`]
+/// counts a centroid as still. Polars' parallel means need not sum in a
+/// fixed order, so a stable partition can still shift centroids by an ulp.
+const FIT_LAZY_TOL: f64 = 1e-9;
+
+/// What to do with rows whose distance to a centroid is NaN, which comes
+/// from NaN or missing feature values.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum NanPolicy {
+    /// Stop at the first NaN or missing distance met during assignment
+    /// with [`Error::NanDistance`], or at a seed with a NaN or missing
+    /// feature with [`Error::NanSeed`]. [`KMeans::eval`] and
+    /// [`KMeans::fit`] panic with the error.
+    #[default]
+    Panic,
+    /// Leave rows with NaN or missing features out of the fit and out of
+    /// every cluster; their ids are listed by [`KMeans::unassigned`].
+    Unassigned,
+}
+
+/// What to do when the data holds fewer distinct feature vectors than the
+/// requested number of clusters, which would leave some clusters empty.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum DistinctPolicy {
+    /// Lower `k` to the number of distinct feature vectors, with a warning.
+    /// [`FitResult::requested_k`] still reports the original `k`.
+    #[default]
+    Reduce,
+    /// Fail with [`Error::TooFewDistinctPoints`].
+    Error,
+}
+
+/// Built-in centroid update, computed per feature inside the lazy engine.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum CentroidUpdate {
+    /// Arithmetic mean of the cluster's points.
+    #[default]
+    Mean,
+    /// Mean of the values left after dropping the lowest and highest
+    /// `fraction` of each feature, between the mean's efficiency and the
+    /// median's robustness. `fraction` lies in `[0, 0.5)`; 0 is the mean.
+    TrimmedMean { fraction: f64 },
+}
+
+impl CentroidUpdate {
+    /// Expression for feature `x` of one cluster's centroid.
+    fn expr(self, x: Expr) -> Expr {
+        match self {
+            CentroidUpdate::Mean => x.mean(),
+            // Quantiles that fall on actual values keep at least the middle
+            // value, however small the cluster.
+            CentroidUpdate::TrimmedMean { fraction } => {
+                let low = x
+                    .clone()
+                    .quantile(lit(fraction), QuantileInterpolOptions::Lower);
+                let high = x
+                    .clone()
+                    .quantile(lit(1.0 - fraction), QuantileInterpolOptions::Higher);
+                x.clone()
+                    .filter(x.clone().gt_eq(low).and(x.lt_eq(high)))
+                    .mean()
+            }
+        }
+    }
+}
+
+/// User-supplied centroid update: maps a cluster's points to its centroid.
+type CenterUpdate = Arc<Mutex<dyn FnMut(&[Vec<f64>]) -> Vec<f64> + Send>>;
+
+/// User-supplied stopping rule, see [`KMeans::with_convergence`].
+type ConvergenceCheck = Arc<Mutex<dyn FnMut(&ConvergenceState) -> bool + Send>>;
+
+/// Every cluster's points, one row per point.
+type ClusterPoints = Vec<Vec<Vec<f64>>>;
+
+#[derive(Clone)]
+pub struct KMeans {
+    df: LazyFrame,
+    features: Vec<String>,
+    point_names: Option<Vec<String>>,
+    clusters: Vec<(u64, LazyFrame)>,
+    centers: Vec<Vec<f64>>,
+    std_devs: Vec<Vec<f64>>,
+    n_clusters: usize,
+    /// `k` as given, kept when [`DistinctPolicy::Reduce`] lowers
+    /// `n_clusters`.
+    requested_k: usize,
+    center_ids: Option<Vec<u64>>,
+    scaler: Option<Scaler>,
+    pca: Option<Pca>,
+    metric: DistanceMetric,
+    sparse: bool,
+    algorithm: Algorithm,
+    init: InitMethod,
+    min_seed_separation: f64,
+    initial_centers: Option<Vec<Vec<f64>>>,
+    seed: Option<u64>,
+    label_change_tol: f64,
+    switch_margin: f64,
+    tie_break: TieBreak,
+    constraints: Option<Constraints>,
+    coincident: CoincidentPolicy,
+    n_init: usize,
+    stability: Option<f64>,
+    iterations: usize,
+    converged: bool,
+    size_history: Vec<Vec<usize>>,
+    fit_sample_frac: f64,
+    excluded: Option<Vec<u64>>,
+    selection: Option<SelectionMetric>,
+    metrics_distance: Option<DistanceMetric>,
+    centroid_update: CentroidUpdate,
+    center_update: Option<CenterUpdate>,
+    convergence: Option<ConvergenceCheck>,
+    shuffle: bool,
+    ranges: Option<Vec<f64>>,
+    validation_frac: f64,
+    unassigned: Vec<u64>,
+    write_distances: bool,
+    write_step_clusters: bool,
+    write_step_centroids: bool,
+    max_output_files: usize,
+    output_dir: String,
+    output_prefix: String,
+    output_names: OutputNames,
+    csv_options: CsvWriterOptions,
+}
+
+impl KMeans {
+    pub fn new(
+        df: LazyFrame,
+        n_clusters: impl Into<usize>,
+        center_ids: Option<Vec<u64>>,
+        io: bool,
+        csv_options: CsvWriterOptions,
+    ) -> Result<Self, Error> {
+        let n_clusters = n_clusters.into();
+        let features = Self::feature_columns(&df);
+
+        let center_ids = match center_ids {
+            Some(center_ids) => Some(Self::validate_center_ids(&df, n_clusters, center_ids)?),
+            None => None,
+        };
+
+        Ok(KMeans {
+            df,
+            features,
+            point_names: None,
+            centers: Vec::new(),
+            std_devs: Vec::new(),
+            clusters: Vec::new(),
+            n_clusters,
+            requested_k: n_clusters,
+            center_ids,
+            scaler: None,
+            pca: None,
+            metric: DistanceMetric::default(),
+            sparse: false,
+            algorithm: Algorithm::default(),
+            init: InitMethod::default(),
+            min_seed_separation: 0.0,
+            initial_centers: None,
+            seed: None,
+            label_change_tol: 0.0,
+            switch_margin: 0.0,
+            tie_break: TieBreak::default(),
+            constraints: None,
+            coincident: CoincidentPolicy::default(),
+            n_init: 1,
+            stability: None,
+            iterations: 0,
+            converged: false,
+            size_history: Vec::new(),
+            fit_sample_frac: 1.0,
+            excluded: None,
+            selection: None,
+            metrics_distance: None,
+            centroid_update: CentroidUpdate::default(),
+            center_update: None,
+            convergence: None,
+            shuffle: false,
+            ranges: None,
+            validation_frac: 0.0,
+            unassigned: Vec::new(),
+            write_distances: io,
+            write_step_clusters: io,
+            write_step_centroids: false,
+            max_output_files: DEFAULT_MAX_OUTPUT_FILES,
+            output_dir: RESULT_DIRECTORY.to_owned(),
+            output_prefix: String::new(),
+            output_names: OutputNames::default(),
+            csv_options,
+        })
+    }
+
+    /// Deduplicates `center_ids` and checks there is exactly one per cluster
+    /// and that each refers to an existing row.
+    fn validate_center_ids(
+        df: &LazyFrame,
+        n_clusters: usize,
+        center_ids: Vec<u64>,
+    ) -> Result<Vec<u64>, Error> {
+        let mut seen = HashSet::new();
+        let center_ids = center_ids
+            .into_iter()
+            .filter(|id| seen.insert(*id))
+            .collect::<Vec<_>>();
+
+        if center_ids.len() != n_clusters {
+            return Err(Error::CenterIdsLength {
+                expected: n_clusters,
+                found: center_ids.len(),
+            });
+        }
+
+        let unknown = Self::unknown_ids(df, &center_ids);
+        if !unknown.is_empty() {
+            return Err(Error::UnknownCenterIds(unknown));
+        }
+
+        Ok(center_ids)
+    }
+
+    /// The `ids` no row of `df` has as its `n`.
+    fn unknown_ids(df: &LazyFrame, ids: &[u64]) -> Vec<u64> {
+        let found = df
+            .clone()
+            .filter(col("n").is_in(lit(ids.iter().copied().collect::<Series>())))
+            .select([col("n")])
+            .collect()
+            .unwrap();
+        let found = found
+            .column("n")
+            .unwrap()
+            .u64()
+            .unwrap()
+            .into_no_null_iter()
+            .collect::<HashSet<_>>();
+
+        ids.iter()
+            .copied()
+            .filter(|id| !found.contains(id))
+            .collect()
+    }
+
+    /// Derives the feature columns from the raw ones, e.g. a log transform or
+    /// polar to Cartesian coordinates: the data becomes `n` plus one column
+    /// per expression, named by its alias, and only those are clustered and
+    /// written out. Must be called before [`with_scaling`](Self::with_scaling)
+    /// and [`with_pca`](Self::with_pca).
+    pub fn with_feature_transform(mut self, exprs: Vec<Expr>) -> Self {
+        assert!(
+            self.scaler.is_none() && self.pca.is_none(),
+            "with_feature_transform must be called before with_scaling and with_pca"
+        );
+        let mut columns = vec![col("n")];
+        columns.extend(exprs);
+
+        self.df = self.df.select(columns);
+        self.features = Self::feature_columns(&self.df);
+
+        self
+    }
+
+    /// Clusters the features instead of the rows, e.g. to group correlated
+    /// variables: every feature becomes a point whose coordinates are its
+    /// values over the observations, in columns `obs{n}`. The points get a
+    /// fresh `n` from 0 in feature order; [`feature_labels`](Self::feature_labels)
+    /// maps the resulting clusters back to feature names. Collects the data.
+    /// Must be called before [`with_scaling`](Self::with_scaling) and
+    /// [`with_pca`](Self::with_pca), and not with `center_ids`.
+    pub fn with_transpose(mut self, transpose: bool) -> Self {
+        if !transpose {
+            return self;
+        }
+        assert!(
+            self.scaler.is_none() && self.pca.is_none(),
+            "with_transpose must be called before with_scaling and with_pca"
+        );
+        assert!(
+            self.center_ids.is_none(),
+            "center_ids refer to rows and cannot be used with with_transpose"
+        );
+
+        let df = self.df.clone().collect().unwrap();
+        let names = df
+            .column("n")
+            .unwrap()
+            .u64()
+            .unwrap()
+            .into_no_null_iter()
+            .map(|n| format!("obs{n}"))
+            .collect::<Vec<_>>();
+        let features = self
+            .features
+            .iter()
+            .map(|f| df.column(f).unwrap().cast(&DataType::Float64).unwrap())
+            .collect::<Vec<_>>();
+
+        let mut transposed = DataFrame::new(features)
+            .unwrap()
+            .transpose(None, None)
+            .unwrap();
+        transposed.set_column_names(&names).unwrap();
+        let transposed = DataFrame::new(vec![Series::new(
+            "n",
+            (0..self.features.len() as u64).collect::<Vec<_>>(),
+        )])
+        .unwrap()
+        .hstack(transposed.get_columns())
+        .unwrap();
+
+        self.df = transposed.lazy();
+        self.point_names = Some(std::mem::replace(
+            &mut self.features,
+            Self::feature_columns(&self.df),
+        ));
+
+        self
+    }
+
+    /// `(feature, cluster)` for every original feature of a
+    /// [`with_transpose`](Self::with_transpose) fit, in feature order.
+    /// Panics if the fit was not transposed.
+    pub fn feature_labels(&self, clusters: &[(u64, LazyFrame)]) -> Vec<(String, u64)> {
+        let names = self
+            .point_names
+            .as_ref()
+            .expect("feature_labels requires with_transpose");
+
+        into_labels(clusters, "n")
+            .into_iter()
+            .map(|(n, cluster)| (names[n as usize].clone(), cluster))
+            .collect()
+    }
+
+    /// Rescales every feature column before clustering. The fitted scaler is
+    /// kept, exposed through [`KMeans::scaler`] and reapplied by
+    /// [`transform`](Self::transform) and [`predict`](Self::predict). Must
+    /// be called before [`with_pca`](Self::with_pca).
+    pub fn with_scaling(mut self, scaling: Scaling) -> Self {
+        assert!(
+            self.pca.is_none(),
+            "with_scaling must be called before with_pca"
+        );
+        let scaler = Scaler::fit(self.df.clone(), &self.features, scaling);
+
+        self.df = scaler.transform(self.df);
+        self.scaler = Some(scaler);
+
+        self
+    }
+
+    pub fn scaler(&self) -> Option<&Scaler> {
+        self.scaler.as_ref()
+    }
+
+    /// Projects the data onto its first `n_components` principal components
+    /// and clusters in that space. The fitted projection is kept and can be
+    /// applied to new data through [`KMeans::pca`].
+    pub fn with_pca(mut self, n_components: usize) -> Self {
+        let pca = Pca::fit(self.df.clone(), &self.features, n_components);
+
+        self.df = pca.transform(self.df);
+        self.features = Self::feature_columns(&self.df);
+        self.pca = Some(pca);
+
+        self
+    }
+
+    /// Guards against high-dimensional input, where distances lose contrast:
+    /// with more than `max_dims` features the data is projected onto
+    /// `target_dims` principal components as by [`with_pca`](Self::with_pca)
+    /// and a warning is printed. Below the threshold this does nothing.
+    pub fn with_max_dims(self, max_dims: usize, target_dims: usize) -> Self {
+        if self.features.len() <= max_dims {
+            return self;
+        }
+
+        eprintln!(
+            "warning: {} features exceed max_dims = {max_dims}, applying PCA down to {target_dims} components",
+            self.features.len()
+        );
+        self.with_pca(target_dims)
+    }
+
+    pub fn pca(&self) -> Option<&Pca> {
+        self.pca.as_ref()
+    }
+
+    /// Computes the iteration distances in Rust over the non-zero feature
+    /// values only, which is much cheaper for mostly-zero data such as wide
+    /// one-hot columns. The features are copied once in sparse form.
+    pub fn with_sparse(mut self, sparse: bool) -> Self {
+        self.sparse = sparse;
+
+        self
+    }
+
+    /// Chooses the assignment strategy, [`Algorithm::Auto`] by default.
+    /// [`Algorithm::Elkan`] computes the iterations in Rust on a copy of the
+    /// features and reaches the same clustering with far fewer distance
+    /// evaluations when there are many clusters in few dimensions; it takes
+    /// precedence over [`with_sparse`](Self::with_sparse), writes no
+    /// per-step distance files and is ignored for
+    /// [`DistanceMetric::KLDivergence`].
+    pub fn with_algorithm(mut self, algorithm: Algorithm) -> Self {
+        self.algorithm = algorithm;
+
+        self
+    }
+
+    /// Chooses how seeds are picked when no `center_ids` are given.
+    pub fn with_init(mut self, init: InitMethod) -> Self {
+        self.init = init;
+
+        self
+    }
+
+    /// Makes k-means++ seeding redraw a candidate that lies within
+    /// `separation` of an already chosen seed, measured with the fit's
+    /// metric (on range-normalised values under
+    /// [`DistanceMetric::RangeNormalized`]). After 32 draws the farthest
+    /// candidate is kept, so on small or tight data, where fewer than k
+    /// points are that far apart, seeds can still end up closer. Has no
+    /// effect on other initialisations.
+    pub fn with_min_seed_separation(mut self, separation: f64) -> Self {
+        assert!(
+            separation >= 0.0,
+            "min_seed_separation must be non-negative"
+        );
+        self.min_seed_separation = separation;
+
+        self
+    }
+
+    /// Starts from the given centroids instead of picking seeds, e.g. from
+    /// [`FitResult::split_largest`] to warm-start a sweep over `k`. Takes
+    /// precedence over `center_ids` and the init method.
+    pub fn with_initial_centers(mut self, centers: Vec<Vec<f64>>) -> Self {
+        assert!(
+            centers.iter().all(|c| c.len() == self.features.len()),
+            "initial centers must have one coordinate per feature"
+        );
+        self.initial_centers = Some(centers);
+
+        self
+    }
+
+    /// Reads the initial centroids from a CSV file with one row per cluster
+    /// and one column per feature, in any order; an `n` column is ignored.
+    /// See [`with_initial_centers`](Self::with_initial_centers).
+    pub fn with_centers_file(self, path: impl Into<PathBuf>) -> Result<Self, Error> {
+        let df = CsvInput::new(path).load()?;
+
+        let mut found = Self::feature_columns(&df);
+        let mut expected = self.features.clone();
+        found.sort();
+        expected.sort();
+        if found != expected {
+            return Err(Error::CenterColumns { expected, found });
+        }
+
+        let centers = points(&df.select(self.features.iter().map(|f| col(f)).collect::<Vec<_>>()));
+        if centers.len() != self.n_clusters {
+            return Err(Error::CentersLength {
+                expected: self.n_clusters,
+                found: centers.len(),
+            });
+        }
+
+        Ok(self.with_initial_centers(centers))
+    }
+
+    /// Seeds the random number generator used for initialisation, making
+    /// fits reproducible.
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+
+        self
+    }
+
+    /// Stops iterating once the fraction of points whose label changed since
+    /// the previous iteration is at most `tol`, e.g. 0.001 to accept 99.9%
+    /// stable labels. The default of 0.0 stops only when no label changes.
+    pub fn with_label_change_tol(mut self, tol: f64) -> Self {
+        self.label_change_tol = tol;
+
+        self
+    }
+
+    /// Hysteresis for boundary points: after the first iteration a point
+    /// only moves to a new nearest centroid when it is closer than the
+    /// point's current centroid by more than `margin`. The default of 0.0
+    /// is plain nearest-centroid assignment. A positive margin disables
+    /// [`Algorithm::Elkan`], which does not keep every distance.
+    pub fn with_switch_margin(mut self, margin: f64) -> Self {
+        assert!(margin >= 0.0, "switch_margin must be non-negative");
+        self.switch_margin = margin;
+
+        self
+    }
+
+    /// Chooses which centroid takes a point at exactly equal distance from
+    /// two of them, by cluster size after the previous step. Ties are
+    /// common on integer data, so a policy other than the default
+    /// [`TieBreak::LowestIndex`] can shift boundary points, and with them
+    /// the number of steps and the final partition. It applies while
+    /// fitting only; [`predict`](Self::predict) keeps the lowest index. A
+    /// size-based policy disables [`Algorithm::Elkan`].
+    pub fn with_tie_break(mut self, tie_break: TieBreak) -> Self {
+        self.tie_break = tie_break;
+
+        self
+    }
+
+    /// Semi-supervised COP-KMeans: rows paired in `must_link` end every
+    /// step in the same cluster and rows paired in `cannot_link` in
+    /// different ones, by `n`. Each step, constrained rows go to their
+    /// nearest centroid that keeps the constraints, must-linked rows as one
+    /// group. Fails on ids missing from the data or on a cannot-link pair
+    /// joined by must-links. The greedy assignment can still run out of
+    /// centroids, e.g. with more mutually cannot-linked rows than
+    /// clusters, and then [`try_eval`](Self::try_eval) fails with
+    /// [`Error::InfeasibleConstraints`]. Constraints need every distance, which
+    /// disables [`Algorithm::Elkan`] and the in-engine assignment.
+    pub fn with_constraints(
+        mut self,
+        must_link: Vec<(u64, u64)>,
+        cannot_link: Vec<(u64, u64)>,
+    ) -> Result<Self, Error> {
+        let constraints = Constraints::new(must_link, cannot_link)?;
+
+        let mut ids = constraints.ids().collect::<Vec<_>>();
+        ids.sort_unstable();
+        ids.dedup();
+        let unknown = Self::unknown_ids(&self.df, &ids);
+        if !unknown.is_empty() {
+            return Err(Error::UnknownConstraintIds(unknown));
+        }
+        self.constraints = Some(constraints);
+
+        Ok(self)
+    }
+
+    /// Chooses how two centroids that converge onto the same coordinates are
+    /// separated. The default merges them, reducing the number of clusters.
+    pub fn with_coincident_policy(mut self, policy: CoincidentPolicy) -> Self {
+        self.coincident = policy;
+
+        self
+    }
+
+    /// Chooses the built-in centroid update. A closure given to
+    /// [`with_center_update`](Self::with_center_update) takes precedence.
+    pub fn with_centroid_update(mut self, update: CentroidUpdate) -> Self {
+        if let CentroidUpdate::TrimmedMean { fraction } = update {
+            assert!(
+                (0.0..0.5).contains(&fraction),
+                "trimmed mean fraction must be in [0, 0.5), got {fraction}"
+            );
+        }
+        self.centroid_update = update;
+
+        self
+    }
+
+    /// Replaces the arithmetic mean as the centroid update, e.g. with a
+    /// trimmed mean or geometric median. `update` receives the points of one
+    /// non-empty cluster in feature order and must return a centroid with
+    /// one coordinate per feature. Restarts share the closure, calling it
+    /// one cluster at a time.
+    pub fn with_center_update(
+        mut self,
+        update: impl FnMut(&[Vec<f64>]) -> Vec<f64> + Send + 'static,
+    ) -> Self {
+        self.center_update = Some(Arc::new(Mutex::new(update)));
+
+        self
+    }
+
+    /// Replaces the built-in convergence check, label stability up to
+    /// [`with_label_change_tol`](Self::with_label_change_tol), with
+    /// `converged`. It is called after every step with a
+    /// [`ConvergenceState`] and the loop stops as soon as it returns true,
+    /// which is also what [`converged`](Self::converged) reports. The loop
+    /// still stops once a single centroid is left. Restarts share the
+    /// closure.
+    pub fn with_convergence(
+        mut self,
+        converged: impl FnMut(&ConvergenceState) -> bool + Send + 'static,
+    ) -> Self {
+        self.convergence = Some(Arc::new(Mutex::new(converged)));
+
+        self
+    }
+
+    /// Runs the clustering `n_init` times from different seeds and keeps the
+    /// best run. A fixed seed `s` is used as `s`, `s + 1`, ... per restart.
+    pub fn with_n_init(mut self, n_init: usize) -> Self {
+        self.n_init = n_init;
+
+        self
+    }
+
+    /// Fits the centroids on a random `frac` of the rows, then assigns every
+    /// row to its nearest centroid in one final pass. Sampling uses the
+    /// configured seed; rows named in `center_ids` are always kept.
+    pub fn with_fit_sample_frac(mut self, frac: f64) -> Self {
+        assert!(
+            frac > 0.0 && frac <= 1.0,
+            "fit_sample_frac must be in (0, 1]"
+        );
+        self.fit_sample_frac = frac;
+
+        self
+    }
+
+    /// Shuffles the rows with the configured seed before fitting, so input
+    /// sorted by label does not bias initialisation or sampling. Rows keep
+    /// their `n`, so the output labels still refer to the original rows:
+    /// shuffling changes only the internal processing order. Cluster frames
+    /// come back sorted by `n`, as without shuffling.
+    pub fn with_shuffle(mut self, shuffle: bool) -> Self {
+        self.shuffle = shuffle;
+
+        self
+    }
+
+    /// Holds out a seeded random `frac` of the rows in [`fit`](Self::fit):
+    /// the centroids are fitted on the rest and the held-out rows are
+    /// assigned afterwards into [`FitResult::validation`], where
+    /// [`sweep_k`](crate::sweep_k) scores them instead of the training rows.
+    /// Rows named in `center_ids` always stay in training.
+    pub fn with_validation_frac(mut self, frac: f64) -> Self {
+        assert!(
+            (0.0..1.0).contains(&frac),
+            "validation_frac must be in [0, 1)"
+        );
+        self.validation_frac = frac;
+
+        self
+    }
+
+    /// Treats `column` as a mask of known noise: rows where it is true (or
+    /// non-zero) do not move the centroids but are still assigned to their
+    /// nearest final centroid. The column is dropped from the features, so
+    /// this must be called before [`with_pca`](Self::with_pca).
+    pub fn with_exclude_mask(mut self, column: &str) -> Self {
+        let excluded = self
+            .df
+            .clone()
+            .filter(col(column).cast(DataType::Boolean))
+            .select([col("n")])
+            .collect()
+            .unwrap();
+        self.excluded = Some(
+            excluded
+                .column("n")
+                .unwrap()
+                .u64()
+                .unwrap()
+                .into_no_null_iter()
+                .collect(),
+        );
+
+        self.df = self.df.drop_columns([column]);
+        self.features = Self::feature_columns(&self.df);
+
+        self
+    }
+
+    /// Chooses how rows with NaN or missing features are handled. The
+    /// default panics during assignment rather than silently putting them
+    /// in cluster 0. Must be called before
+    /// [`with_scaling`](Self::with_scaling) and [`with_pca`](Self::with_pca),
+    /// which would spread the NaN to every row.
+    pub fn with_nan_policy(mut self, policy: NanPolicy) -> Self {
+        assert!(
+            self.scaler.is_none() && self.pca.is_none(),
+            "with_nan_policy must be called before with_scaling and with_pca"
+        );
+        if policy == NanPolicy::Panic {
+            return self;
+        }
+
+        let missing = self
+            .features
+            .iter()
+            .map(|f| {
+                let value = col(f).cast(DataType::Float64);
+                value.clone().is_null().or(value.is_nan())
+            })
+            .reduce(|acc, e| acc.or(e))
+            .unwrap();
+
+        let unassigned = self
+            .df
+            .clone()
+            .filter(missing.clone())
+            .select([col("n")])
+            .collect()
+            .unwrap();
+        self.unassigned = unassigned
+            .column("n")
+            .unwrap()
+            .u64()
+            .unwrap()
+            .into_no_null_iter()
+            .collect();
+
+        if !self.unassigned.is_empty() {
+            eprintln!(
+                "warning: {} rows with NaN or missing features are left unassigned",
+                self.unassigned.len()
+            );
+            self.df = self.df.filter(missing.not());
+        }
+
+        self
+    }
+
+    /// Counts the distinct feature vectors and, if there are fewer than
+    /// `k`, handles it per `policy`. Center ids and initial centers fix `k`,
+    /// so with either [`DistinctPolicy::Reduce`] fails as
+    /// [`DistinctPolicy::Error`] does. Call after
+    /// [`with_nan_policy`](Self::with_nan_policy) so that left-out rows are
+    /// not counted.
+    pub fn with_distinct_policy(mut self, policy: DistinctPolicy) -> Result<Self, Error> {
+        let distinct = self
+            .df
+            .clone()
+            .select(self.features.iter().map(|f| col(f)).collect::<Vec<_>>())
+            .unique(None, UniqueKeepStrategy::Any)
+            .select([count()])
+            .collect()
+            .unwrap();
+        let distinct = distinct.get_columns()[0].idx().unwrap().get(0).unwrap_or(0) as usize;
+        if distinct >= self.n_clusters {
+            return Ok(self);
+        }
+
+        let fixed = self.center_ids.is_some() || self.initial_centers.is_some();
+        if policy == DistinctPolicy::Error || fixed {
+            return Err(Error::TooFewDistinctPoints {
+                k: self.n_clusters,
+                distinct,
+            });
+        }
+
+        eprintln!(
+            "warning: only {distinct} distinct points for {} clusters, reducing k to {distinct}",
+            self.n_clusters
+        );
+        self.n_clusters = distinct;
+
+        Ok(self)
+    }
+
+    /// Ids of the rows left out under [`NanPolicy::Unassigned`].
+    pub fn unassigned(&self) -> &[u64] {
+        &self.unassigned
+    }
+
+    /// Overrides the metric used to choose among `n_init` restarts. The
+    /// final fit is also scored under it, see [`FitResult::selection_score`].
+    pub fn with_selection(mut self, selection: SelectionMetric) -> Self {
+        self.selection = Some(selection);
+
+        self
+    }
+
+    /// Overrides the distance the quality metrics are measured under: the
+    /// restart and [`selection_score`](FitResult::selection_score) scores
+    /// and [`fit_with_metrics`](Self::fit_with_metrics). By default they use
+    /// the clustering's own [`with_metric`](Self::with_metric), so e.g. a KL
+    /// clustering is not judged by Euclidean separation; pass
+    /// [`DistanceMetric::Euclidean`] to get the free functions' behaviour.
+    pub fn with_metrics_distance(mut self, distance: DistanceMetric) -> Self {
+        self.metrics_distance = Some(distance);
+
+        self
+    }
+
+    /// Toggles the per-step `distances` files, which hold every row's
+    /// distance to each centroid and are by far the largest output. `io` in
+    /// [`KMeans::new`] sets this and
+    /// [`with_write_step_clusters`](Self::with_write_step_clusters) together.
+    pub fn with_write_distances(mut self, write: bool) -> Self {
+        self.write_distances = write;
+
+        self
+    }
+
+    /// Toggles the per-step snapshot of every cluster's rows.
+    pub fn with_write_step_clusters(mut self, write: bool) -> Self {
+        self.write_step_clusters = write;
+
+        self
+    }
+
+    /// Toggles a per-step file of every centroid's coordinates, keyed by
+    /// cluster id, for tracing how centroids move. Off by default and not
+    /// affected by `io` in [`KMeans::new`].
+    pub fn with_write_step_centroids(mut self, write: bool) -> Self {
+        self.write_step_centroids = write;
+
+        self
+    }
+
+    /// Caps the per-step files (distances, cluster snapshots and centroids)
+    /// a single `eval` may write, 10 000 by default, so a slow-converging
+    /// run at high `k` cannot fill the disk. Before a step whose files would
+    /// exceed the cap, and before writing any of them,
+    /// [`try_eval`](Self::try_eval) fails with [`Error::TooManyOutputFiles`].
+    /// Restarts overwrite the same files and are counted separately.
+    pub fn with_max_output_files(mut self, max_output_files: usize) -> Self {
+        self.max_output_files = max_output_files;
+
+        self
+    }
+
+    /// Writes the per-step files and the manifest to `dir` instead of
+    /// [`RESULT_DIRECTORY`]. The directory must exist.
+    pub fn with_output_dir(mut self, dir: &str) -> Self {
+        self.output_dir = dir.to_owned();
+
+        self
+    }
+
+    /// Prepends `prefix` to every file written during `eval`, so several runs
+    /// can share one output directory. It fills the `{run}` placeholder of
+    /// the [`OutputNames`] templates.
+    pub fn with_output_prefix(mut self, prefix: &str) -> Self {
+        self.output_prefix = prefix.to_owned();
+
+        self
+    }
+
+    /// Overrides the names of the per-step files written during `eval`.
+    pub fn with_output_names(mut self, names: OutputNames) -> Self {
+        self.output_names = names;
+
+        self
+    }
+
+    /// Panics if the data is not valid for `metric`, e.g. negative values
+    /// under [`DistanceMetric::KLDivergence`]. The ranges for
+    /// [`DistanceMetric::RangeNormalized`] are measured when fitting.
+    pub fn with_metric(mut self, metric: DistanceMetric) -> Self {
+        metric.validate(&self.df, &self.features);
+        self.metric = metric;
+
+        self
+    }
+
+    /// Runs the clustering and returns each cluster's frame paired with its
+    /// id, i.e. the value of the `cluster` label its rows were assigned.
+    /// Clusters are numbered by their centroid in lexicographic coordinate
+    /// order, so ids and centroid order are stable across runs that reach
+    /// the same partition. If every row has the same coordinates the data
+    /// cannot be split and a single cluster is returned.
+    ///
+    /// Iteration stops once an assignment reproduces the previous partition
+    /// (the same groups of rows, whatever their labels), once at most
+    /// `label_change_tol` of the labels changed, or once one centroid is
+    /// left. Centroid coordinates are never compared for equality.
+    ///
+    /// With `n_init` above one the clustering is restarted that many times
+    /// and the best run according to the selection metric is kept. Per-step
+    /// output files then reflect the last restart, not necessarily the best.
+    ///
+    /// With a `fit_sample_frac` below one or an exclusion mask the
+    /// iterations, and their per-step output files, only cover the rows used
+    /// for fitting; the returned clusters cover every row.
+    ///
+    /// Panics where [`try_eval`](Self::try_eval) would fail.
+    pub fn eval(&mut self) -> Vec<(u64, LazyFrame)> {
+        self.try_eval().unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    /// [`eval`](Self::eval), failing with [`Error::NonNumericFeature`] if a
+    /// feature column left after the builders, such as
+    /// [`with_exclude_mask`](Self::with_exclude_mask), cannot serve as a
+    /// coordinate, with [`Error::InfeasibleConstraints`] if a step cannot
+    /// keep the cannot-link constraints, or with
+    /// [`Error::TooManyOutputFiles`] before a step's files would exceed
+    /// [`with_max_output_files`](Self::with_max_output_files).
+    pub fn try_eval(&mut self) -> Result<Vec<(u64, LazyFrame)>, Error> {
+        self.validate_features()?;
+        self.ranges = self.metric_ranges();
+
+        if !self.shuffle {
+            return self.eval_ordered();
+        }
+
+        let df = self.df.clone();
+        self.df = self.shuffled();
+        let clusters = self.eval_ordered();
+        self.df = df;
+
+        clusters
+    }
+
+    /// Checks that every feature column is numeric. Other types, such as
+    /// text, booleans or dates, would only fail deep in the loop, where
+    /// coordinates are extracted.
+    fn validate_features(&self) -> Result<(), Error> {
+        let schema = self.df.schema().unwrap();
+        if let Some((column, dtype)) = schema
+            .iter()
+            .find(|(name, dtype)| name.as_str() != "n" && !dtype.is_numeric())
+        {
+            return Err(Error::NonNumericFeature {
+                column: column.to_string(),
+                dtype: dtype.to_string(),
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Per-feature ranges the range-normalized metric divides by, with a
+    /// constant feature's range taken as one; `None` under other metrics.
+    fn metric_ranges(&self) -> Option<Vec<f64>> {
+        (self.metric == DistanceMetric::RangeNormalized).then(|| {
+            self.feature_bounds()
+                .into_iter()
+                .map(|(_, min, max)| if max > min { max - min } else { 1.0 })
+                .collect()
+        })
+    }
+
+    /// The rows in a seeded random order, permuting `n` and joining the
+    /// frame back onto it.
+    fn shuffled(&self) -> LazyFrame {
+        let mut ids = self.ids();
+        ids.shuffle(&mut self.rng());
+
+        DataFrame::new(vec![Series::new("n", ids)])
+            .unwrap()
+            .lazy()
+            .left_join(self.df.clone(), col("n"), col("n"))
+    }
+
+    fn eval_ordered(&mut self) -> Result<Vec<(u64, LazyFrame)>, Error> {
+        if self.fit_sample_frac >= 1.0 && self.excluded.is_none() {
+            return self.eval_restarts();
+        }
+
+        let df = self.df.clone();
+        self.df = self.fit_rows();
+        let fitted = self.eval_restarts();
+        self.df = df;
+        fitted?;
+
+        let (ids, labels) = self.nearest_labels(None)?;
+
+        // A centroid can end up without rows once the full data is assigned;
+        // it is dropped and the remaining clusters renumbered.
+        let clusters = self.partition(ids, labels);
+        self.centers = clusters
+            .iter()
+            .map(|(id, _)| self.centers[*id as usize].clone())
+            .collect();
+        self.std_devs = clusters
+            .iter()
+            .map(|(id, _)| self.std_devs[*id as usize].clone())
+            .collect();
+        self.clusters = clusters
+            .into_iter()
+            .enumerate()
+            .map(|(i, (_, lf))| (i as u64, lf))
+            .collect();
+
+        Ok(self.clusters.clone())
+    }
+
+    /// Every `n` with the index of its nearest centroid, in row order,
+    /// computed by Polars in one lazy pass so the distances never leave the
+    /// engine. Fails on a NaN distance, as [`nearest`](Self::nearest) does.
+    /// Ties follow the [`TieBreak`] when `tie_sizes` are given.
+    fn nearest_labels(&self, tie_sizes: Option<&[usize]>) -> Result<(Vec<u64>, Vec<u64>), Error> {
+        let labelled = self.labelled(self.df.clone(), vec![col("n")], tie_sizes)?;
+        let column = |name: &str| {
+            labelled
+                .column(name)
+                .unwrap()
+                .u64()
+                .unwrap()
+                .into_no_null_iter()
+                .collect::<Vec<_>>()
+        };
+
+        Ok((column("n"), column("cluster")))
+    }
+
+    /// `columns` of `df` followed by the nearest centroid in `cluster` and
+    /// the distance to it in `__min_dist`, collected from one lazy pass.
+    /// Fails on a NaN distance, as [`nearest`](Self::nearest) does.
+    fn labelled(
+        &self,
+        df: LazyFrame,
+        mut columns: Vec<Expr>,
+        tie_sizes: Option<&[usize]>,
+    ) -> Result<DataFrame, Error> {
+        // A missing feature gives a null distance, which would never
+        // compare smaller either.
+        let has_nan = (0..self.centers.len())
+            .map(|i| {
+                col(format!("cluster{i}dist").as_str())
+                    .fill_null(lit(f64::NAN))
+                    .is_nan()
+            })
+            .reduce(|acc, e| acc.or(e))
+            .unwrap();
+        columns.extend([
+            col("cluster"),
+            col("__min_dist"),
+            has_nan.alias("__nan"),
+            col("n").alias("__n"),
+        ]);
+
+        let mut labelled = self
+            .with_nearest_label(df, tie_sizes)
+            .select(columns)
+            .collect()
+            .unwrap();
+
+        let ids = labelled.drop_in_place("__n").unwrap();
+        if let Some(i) = labelled
+            .drop_in_place("__nan")
+            .unwrap()
+            .bool()
+            .unwrap()
+            .into_no_null_iter()
+            .position(|nan| nan)
+        {
+            let n = ids.cast(&DataType::UInt64).unwrap().u64().unwrap().get(i);
+            return Err(Error::NanDistance { n: n.unwrap() });
+        }
+
+        Ok(labelled)
+    }
+
+    /// Rows the centroids are fitted on: those not excluded by the mask,
+    /// sampled down to `fit_sample_frac` but keeping at least one per
+    /// cluster. Rows named in `center_ids` are always kept.
+    fn fit_rows(&self) -> LazyFrame {
+        let mut df = self.df.clone();
+        if let Some(excluded) = &self.excluded {
+            let excluded = excluded.iter().copied().collect::<Series>();
+            df = df.filter(col("n").is_in(lit(excluded)).not());
+        }
+
+        let ids = df.select([col("n")]).collect().unwrap();
+        let ids = ids.column("n").unwrap().u64().unwrap();
+
+        let mut sample = if self.fit_sample_frac < 1.0 {
+            let amount = ((ids.len() as f64 * self.fit_sample_frac).ceil() as usize)
+                .max(self.n_clusters)
+                .min(ids.len());
+            rand::seq::index::sample(&mut self.rng(), ids.len(), amount)
+                .into_iter()
+                .map(|i| ids.get(i).unwrap())
+                .collect::<Vec<_>>()
+        } else {
+            ids.into_no_null_iter().collect()
+        };
+        sample.extend(self.center_ids.iter().flatten());
+
+        self.df
+            .clone()
+            .filter(col("n").is_in(lit(sample.into_iter().collect::<Series>())))
+    }
+
+    fn eval_restarts(&mut self) -> Result<Vec<(u64, LazyFrame)>, Error> {
+        if self.n_init <= 1 {
+            self.stability = None;
+            return self.run();
+        }
+
+        let seed = self.seed;
+        let selection = self.selection();
+        let mut best: Option<(f64, FitResult, usize, bool)> = None;
+        let mut labelings = Vec::new();
+
+        for i in 0..self.n_init {
+            self.seed = seed.map(|seed| seed + i as u64);
+
+            let clusters = match self.run() {
+                Ok(clusters) => clusters,
+                Err(e) => {
+                    self.seed = seed;
+                    return Err(e);
+                }
+            };
+            labelings.push(Self::labels(&clusters));
+            let points = clusters
+                .iter()
+                .map(|(_, lf)| points(lf))
+                .collect::<Vec<_>>();
+            let score = self.quality(selection, points);
+
+            if best.as_ref().is_none_or(|(best, ..)| score > *best) {
+                let centers = self.centers.clone();
+                best = Some((
+                    score,
+                    FitResult {
+                        clusters,
+                        centers,
+                        std_devs: self.std_devs.clone(),
+                        stability: None,
+                        requested_k: self.requested_k,
+                        validation: None,
+                        selection_score: None,
+                        size_history: self.size_history.clone(),
+                    },
+                    self.iterations,
+                    self.converged,
+                ));
+            }
+        }
+
+        let mut ari = Vec::new();
+        for (i, a) in labelings.iter().enumerate() {
+            for b in &labelings[i + 1..] {
+                ari.push(adjusted_rand_index(a, b));
+            }
+        }
+        self.stability = Some(ari.iter().sum::<f64>() / ari.len() as f64);
+
+        self.seed = seed;
+        let (_, best, iterations, converged) = best.unwrap();
+        self.clusters = best.clusters;
+        self.centers = best.centers;
+        self.std_devs = best.std_devs;
+        self.size_history = best.size_history;
+        self.iterations = iterations;
+        self.converged = converged;
+
+        Ok(self.clusters.clone())
+    }
+
+    /// Row ids and the distance of every row to each centroid, computed by
+    /// Polars.
+    fn dense_distances(&self, step: usize) -> (Vec<u64>, Vec<Vec<f64>>) {
+        let df_clusters = self.df.clone().with_columns(self.distance_exprs());
+
+        if self.write_distances {
+            let _ = df_clusters.clone().sink_csv(
+                format!(
+                    "{}/{}",
+                    self.output_dir,
+                    self.output_names.distances(&self.output_prefix, step)
+                )
+                .into(),
+                self.csv_options.clone(),
+            );
+        }
+
+        let df_num = df_clusters
+            .clone()
+            .select(&[col("n")])
+            .collect()
+            .unwrap()
+            .iter()
+            .map(|s| s.u64().unwrap().into_no_null_iter().collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+
+        let df_num = df_num[0].clone();
+
+        let clusters_dist = df_clusters
+            .clone()
+            .select(&[col("*").exclude(self.features.iter().map(String::as_str).chain(["n"]))])
+            .collect()
+            .unwrap()
+            .iter()
+            .map(|s| {
+                s.f64()
+                    .unwrap()
+                    .into_iter()
+                    .map(|d| d.unwrap_or(f64::NAN))
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+
+        (df_num, clusters_dist)
+    }
+
+    /// [`nearest_labels`](Self::nearest_labels), writing the step's distance
+    /// file first if enabled.
+    fn dense_labels(
+        &self,
+        step: usize,
+        tie_sizes: Option<&[usize]>,
+    ) -> Result<(Vec<u64>, Vec<u64>), Error> {
+        if self.write_distances {
+            let _ = self
+                .df
+                .clone()
+                .with_columns(self.distance_exprs())
+                .sink_csv(
+                    format!(
+                        "{}/{}",
+                        self.output_dir,
+                        self.output_names.distances(&self.output_prefix, step)
+                    )
+                    .into(),
+                    self.csv_options.clone(),
+                );
+        }
+
+        self.nearest_labels(tie_sizes)
+    }
+
+    /// Same as [`dense_distances`](Self::dense_distances) over a sparse copy
+    /// of the features.
+    fn sparse_distances(
+        &self,
+        rows: &SparseRows,
+        ids: &[u64],
+        step: usize,
+    ) -> (Vec<u64>, Vec<Vec<f64>>) {
+        let clusters_dist = match &self.ranges {
+            Some(ranges) => rows.distances(
+                &self
+                    .centers
+                    .iter()
+                    .map(|c| c.iter().zip(ranges).map(|(c, r)| c / r).collect())
+                    .collect::<Vec<_>>(),
+                DistanceMetric::Euclidean,
+            ),
+            None => rows.distances(&self.centers, self.metric),
+        };
+
+        if self.write_distances {
+            let mut columns = vec![Series::new("n", ids)];
+            columns.extend(
+                clusters_dist
+                    .iter()
+                    .enumerate()
+                    .map(|(i, dist)| Series::new(&format!("cluster{}dist", i), dist)),
+            );
+
+            let _ = DataFrame::new(columns).unwrap().lazy().sink_csv(
+                format!(
+                    "{}/{}",
+                    self.output_dir,
+                    self.output_names.distances(&self.output_prefix, step)
+                )
+                .into(),
+                self.csv_options.clone(),
+            );
+        }
+
+        (ids.to_vec(), clusters_dist)
+    }
+
+    /// Values of `n`, in row order.
+    fn ids(&self) -> Vec<u64> {
+        self.df
+            .clone()
+            .select([col("n")])
+            .collect()
+            .unwrap()
+            .column("n")
+            .unwrap()
+            .u64()
+            .unwrap()
+            .into_no_null_iter()
+            .collect()
+    }
+
+    /// Mean pairwise adjusted Rand index between the restarts of the last
+    /// `eval`, see [`FitResult::stability`].
+    pub fn stability(&self) -> Option<f64> {
+        self.stability
+    }
+
+    /// Assignment steps taken by the last `eval`, or by its best restart.
+    pub fn iterations(&self) -> usize {
+        self.iterations
+    }
+
+    /// Size of every cluster after each assignment step of the last `eval`,
+    /// or of its best restart: one row per step, one entry per centroid of
+    /// that step, empty clusters included as 0. Sizes that keep swinging
+    /// between steps point at an unstable fit or a bad seed. Empty when the
+    /// data is constant and no step runs.
+    pub fn size_history(&self) -> &[Vec<usize>] {
+        &self.size_history
+    }
+
+    /// Whether the last `eval` stopped because the partition no longer
+    /// changed, rather than on `label_change_tol` or with one centroid left.
+    pub fn converged(&self) -> bool {
+        self.converged
+    }
+
+    /// Writes a JSON manifest of the last `eval` to
+    /// [`OutputNames::manifest`] in the output directory: the crate
+    /// version, a Unix timestamp, `input` (the data's origin, which the
+    /// frame does not record), the configuration and the outcome, enough to
+    /// reproduce or audit the run.
+    pub fn write_manifest(&self, input: &str) -> std::io::Result<()> {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+
+        let manifest = json!({
+            "version": env!("CARGO_PKG_VERSION"),
+            "timestamp": timestamp,
+            "input": input,
+            "seed": self.seed,
+            "k": self.n_clusters,
+            "clusters": self.centers.len(),
+            "metric": self.metric.to_string(),
+            "init": self.init.to_string(),
+            "scaling": self.scaler.as_ref().map(|s| format!("{:?}", s.scaling())),
+            "selection": self.selection().to_string(),
+            "iterations": self.iterations,
+            "converged": self.converged,
+            "stability": self.stability,
+        });
+
+        let path = format!(
+            "{}/{}",
+            self.output_dir,
+            self.output_names.manifest(&self.output_prefix)
+        );
+        serde_json::to_writer_pretty(BufWriter::new(File::create(path)?), &manifest)?;
+
+        Ok(())
+    }
+
+    /// Cluster id of every row, ordered by `n`.
+    fn labels(clusters: &[(u64, LazyFrame)]) -> Vec<u64> {
+        into_labels(clusters, "n")
+            .into_iter()
+            .map(|(_, id)| id)
+            .collect()
+    }
+
+    /// Splits the rows of `df` into one frame per label, given the label of
+    /// every `n` in `ids`, ordered by label with rows in `n` order so that
+    /// written clusters are byte-stable for identical inputs.
+    fn partition(&self, ids: Vec<u64>, labels: Vec<u64>) -> Vec<(u64, LazyFrame)> {
+        let s1 = Series::new("n", ids);
+        let s2 = Series::new("cluster", labels);
+
+        let df_clusters = DataFrame::new(vec![s1, s2]).unwrap().lazy();
+
+        let df = self
+            .df
+            .clone()
+            .left_join(df_clusters, col("n"), col("n"))
+            .sort("n", SortOptions::default());
+
+        let mut clusters = df
+            .collect()
+            .unwrap()
+            .partition_by_stable(["cluster"], true)
+            .unwrap()
+            .into_iter()
+            .map(|mut x| {
+                let id = x
+                    .drop_in_place("cluster")
+                    .unwrap()
+                    .u64()
+                    .unwrap()
+                    .get(0)
+                    .unwrap();
+                (id, x.lazy())
+            })
+            .collect::<Vec<_>>();
+        clusters.sort_by_key(|(id, _)| *id);
+
+        clusters
+    }
+
+    /// `metric` over `clusters` laid out by id, so that a cluster missing
+    /// from a validation split stays aligned with its centroid.
+    fn selection_score(&self, metric: SelectionMetric, clusters: &[(u64, LazyFrame)]) -> f64 {
+        let mut by_id = vec![Vec::new(); self.centers.len()];
+        for (id, lf) in clusters {
+            by_id[*id as usize] = points(lf);
+        }
+
+        self.quality(metric, by_id)
+    }
+
+    fn quality(&self, metric: SelectionMetric, points: ClusterPoints) -> f64 {
+        let (points, centers, distance) = self.metrics_space(points);
+
+        metric.score_with_distance(&points, &centers, distance)
+    }
+
+    /// Points and centroids as the quality metrics should see them, with the
+    /// distance to measure them under. Range-normalised coordinates are
+    /// divided by the fitted ranges and then compared as Euclidean.
+    fn metrics_space(
+        &self,
+        points: ClusterPoints,
+    ) -> (ClusterPoints, Vec<Vec<f64>>, DistanceMetric) {
+        let distance = self.metrics_distance.unwrap_or(self.metric);
+
+        match (distance, &self.ranges) {
+            (DistanceMetric::RangeNormalized, Some(ranges)) => {
+                let scale = |p: &Vec<f64>| p.iter().zip(ranges).map(|(x, r)| x / r).collect();
+                let points = points
+                    .iter()
+                    .map(|cluster| cluster.iter().map(scale).collect())
+                    .collect();
+
+                (
+                    points,
+                    self.centers.iter().map(scale).collect(),
+                    DistanceMetric::Euclidean,
+                )
+            }
+            _ => (points, self.centers.clone(), distance),
+        }
+    }
+
+    /// Criterion used to pick among restarts: the explicit one if set,
+    /// otherwise inertia under [`DistanceMetric::Euclidean`] and the
+    /// silhouette under [`DistanceMetric::KLDivergence`], where Euclidean
+    /// inertia is not the quantity being minimised.
+    pub fn selection(&self) -> SelectionMetric {
+        self.selection.unwrap_or(match self.metric {
+            DistanceMetric::Euclidean | DistanceMetric::RangeNormalized => SelectionMetric::Inertia,
+            DistanceMetric::KLDivergence => SelectionMetric::Silhouette,
+        })
+    }
+
+    /// Resolves the configured [`Algorithm`], see [`Algorithm::Auto`] for
+    /// the heuristic.
+    fn use_elkan(&self) -> bool {
+        if self.metric != DistanceMetric::Euclidean
+            || self.switch_margin > 0.0
+            || self.tie_break != TieBreak::LowestIndex
+            || self.constraints.is_some()
+        {
+            return false;
+        }
+
+        match self.algorithm {
+            Algorithm::Lloyd => false,
+            Algorithm::Elkan => true,
+            Algorithm::Auto => {
+                if self.sparse || self.write_distances || self.features.len() > AUTO_ELKAN_MAX_DIMS
+                {
+                    return false;
+                }
+
+                let n_rows = self.df.clone().select([count()]).collect().unwrap();
+                let n_rows = n_rows.get_columns()[0].idx().unwrap().get(0).unwrap_or(0) as usize;
+
+                n_rows * self.centers.len() <= AUTO_ELKAN_MAX_BOUNDS
+            }
+        }
+    }
+
+    fn run(&mut self) -> Result<Vec<(u64, LazyFrame)>, Error> {
+        if self.is_constant() {
+            self.clusters = vec![(0, self.df.clone())];
+            self.eval_centers();
+            self.iterations = 0;
+            self.converged = true;
+            self.size_history = Vec::new();
+            return Ok(self.clusters.clone());
+        }
+
+        let mut labels_last: Option<Vec<u64>> = None;
+        self.size_history.clear();
+        let mut rng = self.rng();
+        self.centers = self.init_centers()?;
+        self.clusters = vec![(0, self.df.clone())];
+
+        let sparse = self
+            .sparse
+            .then(|| SparseRows::from_frame(&self.range_normalized(), &self.features));
+        let mut elkan = self.use_elkan().then(|| Elkan::new(points(&self.df)));
+        let ids = if sparse.is_some() || elkan.is_some() {
+            self.ids()
+        } else {
+            Vec::new()
+        };
+        // Elkan keeps its distances to itself, so a NaN feature is caught
+        // up front rather than mid-assignment.
+        if let Some(elkan) = &elkan {
+            if let Some(i) = elkan
+                .points()
+                .iter()
+                .position(|p| p.iter().any(|x| x.is_nan()))
+            {
+                return Err(Error::NanDistance { n: ids[i] });
+            }
+        }
+
+        let mut step = 1;
+        // Labels only carry over to the next step while no centroid was
+        // dropped or merged, which would renumber them.
+        let mut assigned_k = 0;
+        let mut output_files = 0;
+
+        loop {
+            // Elkan never materialises the distances, so writes no file.
+            output_files += usize::from(self.write_distances && elkan.is_none())
+                + usize::from(self.write_step_centroids)
+                + if self.write_step_clusters {
+                    self.centers.len()
+                } else {
+                    0
+                };
+            if output_files > self.max_output_files {
+                return Err(Error::TooManyOutputFiles {
+                    step,
+                    max_output_files: self.max_output_files,
+                });
+            }
+
+            let tie_sizes = self.tie_sizes();
+            let ties = tie_sizes.as_deref().map(|sizes| (self.tie_break, sizes));
+            let (df_num, mut cluster_tags, clusters_dist) = match (&mut elkan, &sparse) {
+                (Some(elkan), _) => (ids.clone(), elkan.assign(&self.centers), None),
+                (None, Some(rows)) => {
+                    let (df_num, clusters_dist) = self.sparse_distances(rows, &ids, step);
+                    let cluster_tags = Self::nearest(&clusters_dist, &df_num, ties)?;
+                    (df_num, cluster_tags, Some(clusters_dist))
+                }
+                // Hysteresis and constraints need every distance; otherwise
+                // only the labels leave the lazy engine.
+                (None, None) if self.switch_margin > 0.0 || self.constraints.is_some() => {
+                    let (df_num, clusters_dist) = self.dense_distances(step);
+                    let cluster_tags = Self::nearest(&clusters_dist, &df_num, ties)?;
+                    (df_num, cluster_tags, Some(clusters_dist))
+                }
+                (None, None) => {
+                    let (df_num, cluster_tags) = self.dense_labels(step, tie_sizes.as_deref())?;
+                    (df_num, cluster_tags, None)
+                }
+            };
+
+            if let (Some(last), Some(clusters_dist)) = (&labels_last, &clusters_dist) {
+                if self.switch_margin > 0.0 && assigned_k == self.centers.len() {
+                    Self::keep_within_margin(
+                        &mut cluster_tags,
+                        last,
+                        clusters_dist,
+                        self.switch_margin,
+                    );
+                }
+            }
+            if let (Some(constraints), Some(clusters_dist)) = (&self.constraints, &clusters_dist) {
+                if let Err(row) = constraints.assign(&df_num, clusters_dist, &mut cluster_tags) {
+                    return Err(Error::InfeasibleConstraints {
+                        row,
+                        n_clusters: self.centers.len(),
+                    });
+                }
+            }
+            assigned_k = self.centers.len();
+
+            let mut sizes = vec![0; self.centers.len()];
+            for &label in &cluster_tags {
+                sizes[label as usize] += 1;
+            }
+            self.size_history.push(sizes);
+
+            let labels_changed = native::changed_count(&cluster_tags, labels_last.as_deref());
+            let changed = labels_changed as f64 / cluster_tags.len() as f64;
+            let mut converged = native::converged(&cluster_tags, labels_last.as_deref());
+            labels_last = Some(cluster_tags.clone());
+
+            self.clusters = self.partition(df_num, cluster_tags);
+
+            if self.write_step_clusters {
+                for (i, lf) in &self.clusters {
+                    let _ = lf.clone().sink_csv(
+                        format!(
+                            "{}/{}",
+                            self.output_dir,
+                            self.output_names
+                                .step_cluster(&self.output_prefix, step, *i)
+                        )
+                        .into(),
+                        self.csv_options.clone(),
+                    );
+                }
+            }
+
+            let previous_centers = self.convergence.is_some().then(|| self.centers.clone());
+            self.eval_centers();
+
+            if self.write_step_centroids {
+                self.sink_centroids(step);
+            }
+
+            if let (Some(convergence), Some(previous_centers)) =
+                (&self.convergence, &previous_centers)
+            {
+                let state = ConvergenceState {
+                    step,
+                    previous_centers,
+                    centers: &self.centers,
+                    labels_changed,
+                    inertia: self.inertia_of(&self.labelled(self.df.clone(), Vec::new(), None)?),
+                };
+                converged = convergence.lock().unwrap()(&state);
+            }
+
+            step += 1;
+
+            if native::resolve_coincident(&mut self.centers, self.coincident, &mut rng) {
+                continue;
+            }
+
+            let stable = match self.convergence {
+                Some(_) => converged,
+                None => changed <= self.label_change_tol || converged,
+            };
+            if self.centers.len() <= 1 || stable {
+                self.iterations = step - 1;
+                self.converged = converged;
+                break;
+            }
+        }
+
+        self.sort_clusters();
+
+        Ok(self.clusters.clone())
+    }
+
+    fn sink_centroids(&self, step: usize) {
+        let mut columns = vec![Series::new(
+            "cluster",
+            self.clusters.iter().map(|(id, _)| *id).collect::<Vec<_>>(),
+        )];
+        columns.extend(
+            self.features.iter().enumerate().map(|(j, f)| {
+                Series::new(f, self.centers.iter().map(|c| c[j]).collect::<Vec<_>>())
+            }),
+        );
+
+        let _ = DataFrame::new(columns).unwrap().lazy().sink_csv(
+            format!(
+                "{}/{}",
+                self.output_dir,
+                self.output_names.step_centroids(&self.output_prefix, step)
+            )
+            .into(),
+            self.csv_options.clone(),
+        );
+    }
+
+    /// Fits with [`eval`](Self::eval). With a validation fraction the
+    /// held-out rows are split off first and assigned to the fitted
+    /// centroids afterwards, as [`predict`](Self::predict) would.
+    ///
+    /// Panics where [`try_fit`](Self::try_fit) would fail.
+    pub fn fit(self) -> FitResult {
+        self.try_fit().unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    /// [`fit`](Self::fit), failing where [`try_eval`](Self::try_eval) does.
+    pub fn try_fit(mut self) -> Result<FitResult, Error> {
+        self.fit_in_place()
+    }
+
+    fn fit_in_place(&mut self) -> Result<FitResult, Error> {
+        let validation = self.split_validation();
+        let clusters = self.try_eval()?;
+
+        let validation = validation
+            .map(|df| {
+                let training = std::mem::replace(&mut self.df, df);
+                let labels = self.nearest_labels(None);
+                self.df = training;
+                let (ids, labels) = labels?;
+
+                Ok(self.partition(ids, labels))
+            })
+            .transpose()?;
+        // The cluster frames are still in memory here, so scoring now saves
+        // callers such as the k sweep from collecting them again.
+        let selection_score = self
+            .selection
+            .map(|metric| self.selection_score(metric, validation.as_ref().unwrap_or(&clusters)));
+
+        Ok(FitResult {
+            clusters,
+            centers: self.centers.clone(),
+            std_devs: self.std_devs.clone(),
+            stability: self.stability,
+            requested_k: self.requested_k,
+            selection_score,
+            validation,
+            size_history: self.size_history.clone(),
+        })
+    }
+
+    /// Replaces the data with a seeded training split and returns the
+    /// held-out rows, or `None` without a validation fraction.
+    fn split_validation(&mut self) -> Option<LazyFrame> {
+        if self.validation_frac == 0.0 {
+            return None;
+        }
+
+        let center_ids = self.center_ids.iter().flatten().collect::<HashSet<_>>();
+        let ids = self
+            .ids()
+            .into_iter()
+            .filter(|id| !center_ids.contains(id))
+            .collect::<Vec<_>>();
+        let amount = ((ids.len() as f64 * self.validation_frac).round() as usize)
+            .min(ids.len().saturating_sub(self.n_clusters));
+        let held_out = rand::seq::index::sample(&mut self.rng(), ids.len(), amount)
+            .into_iter()
+            .map(|i| ids[i])
+            .collect::<Series>();
+
+        let is_held_out = col("n").is_in(lit(held_out));
+        let validation = self.df.clone().filter(is_held_out.clone());
+        self.df = self.df.clone().filter(is_held_out.not());
+
+        Some(validation)
+    }
+
+    /// Fits and computes every quality metric in one pass over the final
+    /// clusters, collecting each cluster's features only once. Metrics are
+    /// measured under the clustering's distance unless overridden with
+    /// [`with_metrics_distance`](Self::with_metrics_distance).
+    pub fn fit_with_metrics(mut self) -> (FitResult, MetricsBundle) {
+        let result = self.fit_in_place().unwrap_or_else(|e| panic!("{e}"));
+
+        let points = result
+            .clusters
+            .iter()
+            .map(|(_, lf)| points(lf))
+            .collect::<Vec<_>>();
+        let (points, centers, distance) = self.metrics_space(points);
+        let metrics = MetricsBundle::with_distance(&points, &centers, distance);
+
+        (result, metrics)
+    }
+
+    /// Fits with plain Lloyd iterations and returns every row, with `n`,
+    /// the features and a `cluster` column, as a lazy plan to compose with
+    /// further queries. Each step labels and aggregates the rows in a
+    /// single `group_by` over the in-engine argmin, so the only collect is
+    /// the new centroids; no per-cluster frames are built and nothing is
+    /// written. The configured init, metric, scaling, centroid update and
+    /// coincident policy apply. Iterations stop once the cluster sizes are
+    /// unchanged and no centroid coordinate moved by more than a relative
+    /// `1e-9`, or after 300 steps, in which case
+    /// [`converged`](Self::converged) is false. A centroid left without
+    /// rows is dropped, centroids end up in lexicographic order, and as in
+    /// [`sink_parquet`](Self::sink_parquet) NaN distances are not detected.
+    /// No cluster frames are kept, so methods that read them, such as
+    /// [`add_labels`](Self::add_labels), see no clusters afterwards; the
+    /// returned frame already holds every label. Panics with constraints, a
+    /// switch margin or a custom center update, which need labels or
+    /// cluster points outside the engine.
+    pub fn fit_lazy(&mut self) -> LazyFrame {
+        assert!(
+            self.constraints.is_none() && self.switch_margin == 0.0 && self.center_update.is_none(),
+            "fit_lazy supports neither constraints, a switch margin nor a custom center update"
+        );
+        if let Err(e) = self.validate_features() {
+            panic!("{e}");
+        }
+
+        self.ranges = self.metric_ranges();
+        self.centers = self.init_centers().unwrap_or_else(|e| panic!("{e}"));
+        self.clusters = Vec::new();
+        self.size_history.clear();
+
+        let mut stats = self.center_stats();
+        stats[0] = stats[0].clone().alias("__count");
+        let column = |stats: &DataFrame, name: &str| {
+            stats
+                .column(name)
+                .unwrap()
+                .f64()
+                .unwrap()
+                .into_iter()
+                .map(|v| v.unwrap_or(0.0))
+                .collect::<Vec<_>>()
+        };
+
+        let mut rng = self.rng();
+        let mut step = 0;
+        loop {
+            step += 1;
+
+            // Clusters without rows are absent from the aggregation, which
+            // drops their centroids.
+            let stats = self
+                .with_nearest_label(self.df.clone(), None)
+                .group_by([col("cluster")])
+                .agg(stats.clone())
+                .sort("cluster", SortOptions::default())
+                .collect()
+                .unwrap();
+            let means = self
+                .features
+                .iter()
+                .map(|f| column(&stats, &format!("{f}_mean")))
+                .collect::<Vec<_>>();
+            let stds = self
+                .features
+                .iter()
+                .map(|f| column(&stats, &format!("{f}_std")))
+                .collect::<Vec<_>>();
+
+            let centers = (0..stats.height())
+                .map(|i| means.iter().map(|m| m[i]).collect())
+                .collect::<Vec<Vec<f64>>>();
+            self.std_devs = (0..stats.height())
+                .map(|i| stds.iter().map(|s| s[i]).collect())
+                .collect();
+            let sizes = column(&stats, "__count")
+                .into_iter()
+                .map(|c| c as usize)
+                .collect::<Vec<_>>();
+
+            let still = self.size_history.last() == Some(&sizes)
+                && centers.len() == self.centers.len()
+                && centers.iter().zip(&self.centers).all(|(a, b)| {
+                    a.iter()
+                        .zip(b)
+                        .all(|(x, y)| (x - y).abs() <= FIT_LAZY_TOL * (x.abs().max(y.abs()) + 1.0))
+                });
+            self.size_history.push(sizes);
+            self.centers = centers;
+
+            // Resolving past the cap would leave `std_devs` out of step
+            // with the centroids.
+            let capped = step >= FIT_LAZY_MAX_STEPS;
+            if !capped && native::resolve_coincident(&mut self.centers, self.coincident, &mut rng) {
+                continue;
+            }
+            if still || self.centers.len() <= 1 || capped {
+                self.iterations = step;
+                self.converged = still;
+                break;
+            }
+        }
+
+        let mut pairs = self
+            .centers
+            .drain(..)
+            .zip(self.std_devs.drain(..))
+            .collect::<Vec<_>>();
+        pairs.sort_by(|(a, _), (b, _)| native::lexicographic(a, b));
+        (self.centers, self.std_devs) = pairs.into_iter().unzip();
+
+        let mut columns = vec![col("n")];
+        columns.extend(self.features.iter().map(|f| col(f)));
+        columns.push(col("cluster"));
+
+        self.with_nearest_label(self.df.clone(), None)
+            .select(columns)
+    }
+
+    /// Maps `df` into centroid distance space: one `cluster{i}dist` column
+    /// per fitted centroid, in centroid order. `df` must carry the original
+    /// feature columns; fitted scaling and PCA projection are applied first.
+    pub fn transform(&self, df: LazyFrame) -> DataFrame {
+        self.fitted_space(df)
+            .select(self.distance_exprs())
+            .collect()
+            .unwrap()
+    }
+
+    /// Applies the fitted scaling and PCA projection to `df`.
+    fn fitted_space(&self, df: LazyFrame) -> LazyFrame {
+        let df = match &self.scaler {
+            Some(scaler) => scaler.transform(df),
+            None => df,
+        };
+
+        match &self.pca {
+            Some(pca) => df.with_columns(pca.exprs()),
+            None => df,
+        }
+    }
+
+    /// Labels every row of `df` with the index of its nearest fitted
+    /// centroid, in row order. Panics on a NaN distance.
+    pub fn predict(&self, df: LazyFrame) -> Vec<u64> {
+        self.labelled(self.fitted_space(df), Vec::new(), None)
+            .unwrap_or_else(|e| panic!("{e}"))
+            .column("cluster")
+            .unwrap()
+            .u64()
+            .unwrap()
+            .into_no_null_iter()
+            .collect()
+    }
+
+    /// Negative inertia of `df` under the fitted centroids: minus the sum
+    /// over rows of the squared distance to the nearest centroid, so that
+    /// higher is better as in scikit-learn. With the KL metric the
+    /// divergence itself is summed rather than its square. Panics on a NaN
+    /// distance.
+    pub fn score(&self, df: LazyFrame) -> f64 {
+        let labelled = self.labelled(self.fitted_space(df), Vec::new(), None);
+        -self.inertia_of(&labelled.unwrap_or_else(|e| panic!("{e}")))
+    }
+
+    /// Inertia of a [`labelled`](Self::labelled) frame under the fitted
+    /// metric.
+    fn inertia_of(&self, labelled: &DataFrame) -> f64 {
+        let nearest = labelled.column("__min_dist").unwrap().f64().unwrap();
+
+        match self.metric {
+            DistanceMetric::Euclidean | DistanceMetric::RangeNormalized => {
+                nearest.into_no_null_iter().map(|d| d * d).sum()
+            }
+            DistanceMetric::KLDivergence => nearest.into_no_null_iter().sum(),
+        }
+    }
+
+    /// Ids of every centroid within `assignment.radius` of each row of `df`,
+    /// in row order and ascending id order. A row farther than the radius
+    /// from all centroids gets an empty set rather than its nearest cluster,
+    /// so such outliers stay visible.
+    pub fn predict_within(&self, df: LazyFrame, assignment: RadiusAssignment) -> Vec<Vec<u64>> {
+        let distances = self.distance_frame(self.fitted_space(df));
+        let distances = Self::distance_slices(&distances);
+
+        (0..distances.first().map_or(0, |d| d.len()))
+            .map(|i| {
+                distances
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, d)| d[i] <= assignment.radius)
+                    .map(|(j, _)| j as u64)
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Same as [`predict`](Self::predict), but only `batch_size` rows of
+    /// `df` are materialised at a time, bounding peak memory on large
+    /// inputs.
+    pub fn assign_batched(&self, df: LazyFrame, batch_size: usize) -> Vec<u64> {
+        assert!(batch_size > 0, "batch_size must be positive");
+
+        let n_rows = df
+            .clone()
+            .select([count()])
+            .collect()
+            .unwrap()
+            .get_columns()[0]
+            .idx()
+            .unwrap()
+            .get(0)
+            .unwrap_or(0) as usize;
+
+        (0..n_rows)
+            .step_by(batch_size)
+            .flat_map(|offset| self.predict(df.clone().slice(offset as i64, batch_size as IdxSize)))
+            .collect()
+    }
+
+    /// Distance between every pair of fitted centroids as a k×k frame: a
+    /// `cluster` id column, then `cluster{j}dist` holding the distance from
+    /// centroid `cluster` to centroid `j`. Centroids are compared in the
+    /// fitted space, after any scaling and PCA. KL divergence is asymmetric,
+    /// so the matrix is only symmetric for Euclidean distance.
+    pub fn centroid_distance_matrix(&self, metric: DistanceMetric) -> DataFrame {
+        let mut columns = vec![Series::new(
+            "cluster",
+            (0..self.centers.len() as u64).collect::<Vec<_>>(),
+        )];
+        columns.extend(self.centers.iter().enumerate().map(|(j, b)| {
+            Series::new(
+                format!("cluster{j}dist").as_str(),
+                self.centers
+                    .iter()
+                    .map(|a| match (metric, &self.ranges) {
+                        (DistanceMetric::RangeNormalized, Some(ranges)) => a
+                            .iter()
+                            .zip(b)
+                            .zip(ranges)
+                            .map(|((x, y), r)| ((x - y) / r).powi(2))
+                            .sum::<f64>()
+                            .sqrt(),
+                        _ => metric.distance(a, b),
+                    })
+                    .collect::<Vec<_>>(),
+            )
+        }));
+
+        DataFrame::new(columns).unwrap()
+    }
+
+    /// Name, minimum and maximum of every feature column, computed in one
+    /// lazy aggregation. A constant column has equal bounds. Bounds are in
+    /// PCA space when PCA is enabled.
+    pub fn feature_bounds(&self) -> Vec<(String, f64, f64)> {
+        let exprs = self
+            .features
+            .iter()
+            .flat_map(|f| {
+                [
+                    col(f).min().alias(&format!("{f}_min")),
+                    col(f).max().alias(&format!("{f}_max")),
+                ]
+            })
+            .collect::<Vec<_>>();
+
+        let row = self.df.clone().select(exprs).collect().unwrap();
+        let row = row
+            .get(0)
+            .unwrap()
+            .into_iter()
+            .map(|v| v.try_extract::<f64>().unwrap_or(0.0))
+            .collect::<Vec<_>>();
+
+        self.features
+            .iter()
+            .zip(row.chunks(2))
+            .map(|(f, bounds)| (f.clone(), bounds[0], bounds[1]))
+            .collect()
+    }
+
+    /// Streams every row with a `cluster` column holding its nearest fitted
+    /// centroid to a Parquet file at `path`, without collecting the data or
+    /// splitting it into per-cluster frames. Call after
+    /// [`eval`](Self::eval). The labels are computed lazily with the same
+    /// lowest-index tie rule as assignment, but NaN distances are not
+    /// detected here.
+    pub fn sink_parquet(&self, path: impl Into<PathBuf>) -> PolarsResult<()> {
+        let mut columns = vec![col("n")];
+        columns.extend(self.features.iter().map(|f| col(f)));
+        columns.push(col("cluster"));
+
+        self.with_nearest_label(self.df.clone(), None)
+            .select(columns)
+            .sink_parquet(path.into(), ParquetWriteOptions::default())
+    }
+
+    /// Streams `(n, cluster)` for every row of `df` to a CSV file at `path`
+    /// with the configured CSV options, labelling each row with its nearest
+    /// fitted centroid as [`predict`](Self::predict) would but without
+    /// holding the labels in memory. `df` must carry `n` and the original
+    /// feature columns; like [`sink_parquet`](Self::sink_parquet) NaN
+    /// distances are not detected. Panics on a zero CSV `batch_size`, as in
+    /// `CsvWriterOptions::default()`, on which the sink would never finish.
+    pub fn predict_to_csv(&self, df: LazyFrame, path: impl Into<PathBuf>) -> PolarsResult<()> {
+        assert!(
+            self.csv_options.batch_size > 0,
+            "csv_options.batch_size must be positive"
+        );
+
+        self.with_nearest_label(self.fitted_space(df), None)
+            .select([col("n"), col("cluster")])
+            .sink_csv(path.into(), self.csv_options.clone())
+    }
+
+    /// Adds a `cluster` column with the nearest centroid of every row of
+    /// `df`, computed lazily as an argmin across the `cluster{i}dist`
+    /// columns. Ties go to the lower index, or follow the [`TieBreak`] over
+    /// `tie_sizes`, one per centroid, when given.
+    fn with_nearest_label(&self, df: LazyFrame, tie_sizes: Option<&[usize]>) -> LazyFrame {
+        let mut df = df.with_columns(self.distance_exprs());
+        let dist = |i: usize| col(format!("cluster{i}dist").as_str());
+
+        // One step per centroid, keeping the running minimum in a column so
+        // the expression stays linear in k.
+        df = df.with_columns([lit(0u64).alias("cluster"), dist(0).alias("__min_dist")]);
+        if let Some(sizes) = tie_sizes {
+            df = df.with_column(lit(sizes[0] as u64).alias("__min_size"));
+        }
+        for i in 1..self.centers.len() {
+            let mut closer = dist(i).lt(col("__min_dist"));
+            let mut size = None;
+            if let Some(sizes) = tie_sizes {
+                let size_i = lit(sizes[i] as u64);
+                let prefers = match self.tie_break {
+                    TieBreak::LowestIndex => lit(false),
+                    TieBreak::LargerCluster => size_i.clone().gt(col("__min_size")),
+                    TieBreak::SmallerCluster => size_i.clone().lt(col("__min_size")),
+                };
+                closer = closer.or(dist(i).eq(col("__min_dist")).and(prefers));
+                size = Some(size_i);
+            }
+
+            let mut columns = vec![
+                when(closer.clone())
+                    .then(lit(i as u64))
+                    .otherwise(col("cluster"))
+                    .alias("cluster"),
+                when(closer.clone())
+                    .then(dist(i))
+                    .otherwise(col("__min_dist"))
+                    .alias("__min_dist"),
+            ];
+            columns.extend(size.map(|size| {
+                when(closer)
+                    .then(size)
+                    .otherwise(col("__min_size"))
+                    .alias("__min_size")
+            }));
+            df = df.with_columns(columns);
+        }
+
+        df
+    }
+
+    /// Appends the fitted `cluster` of every row of `df`, matched on `n`,
+    /// in `df`'s own row order whether or not `n` is monotonic, e.g. to
+    /// write labels back next to the input file. Rows the last `eval` did
+    /// not assign, such as those left out under
+    /// [`NanPolicy::Unassigned`], get a null cluster.
+    pub fn add_labels(&self, df: LazyFrame) -> LazyFrame {
+        let (ids, labels): (Vec<u64>, Vec<u64>) =
+            into_labels(&self.clusters, "n").into_iter().unzip();
+        let labels = DataFrame::new(vec![Series::new("n", ids), Series::new("cluster", labels)])
+            .unwrap()
+            .lazy();
+
+        // The join does not promise to keep the input order, so a row
+        // number separate from `n` restores it.
+        df.with_row_count("__row", None)
+            .left_join(labels, col("n"), col("n"))
+            .sort("__row", SortOptions::default())
+            .drop_columns(["__row"])
+    }
+
+    /// Appends a `confidence` column to `df`: the ratio of each row's
+    /// distance to its nearest centroid over the distance to the second
+    /// nearest. Values near 0 are confident assignments, values near 1 sit
+    /// on a boundary between two clusters. With a single centroid every row
+    /// scores 0. `df` must already be in fitted space, like the cluster
+    /// frames [`eval`](Self::eval) returns, so scaling and PCA are not
+    /// applied again.
+    pub fn add_confidence(&self, df: LazyFrame) -> LazyFrame {
+        let distances = self.distance_frame(df.clone());
+        let distances = Self::distance_slices(&distances);
+
+        let confidence = (0..distances.first().map_or(0, |d| d.len()))
+            .map(|i| {
+                let mut nearest = f64::MAX;
+                let mut second = f64::MAX;
+                for dist in distances.iter().map(|d| d[i]) {
+                    if dist < nearest {
+                        second = nearest;
+                        nearest = dist;
+                    } else if dist < second {
+                        second = dist;
+                    }
+                }
+
+                match second {
+                    f64::MAX => 0.0,
+                    s if s > 0.0 => nearest / s,
+                    _ => 1.0,
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let mut df = df.collect().unwrap();
+        df.with_column(Series::new("confidence", confidence))
+            .unwrap();
+
+        df.lazy()
+    }
+
+    /// Soft assignment of every row of `df`: one `cluster{i}prob` column per
+    /// centroid holding `exp(-d_i / temperature)` normalised so each row
+    /// sums to 1. Low temperatures approach the hard assignment of
+    /// [`predict`](Self::predict), high ones a uniform split. Distances are
+    /// those of [`transform`](Self::transform).
+    pub fn predict_proba(&self, df: LazyFrame, temperature: f64) -> DataFrame {
+        assert!(
+            temperature > 0.0,
+            "temperature must be positive, got {temperature}"
+        );
+
+        // Shifting by the nearest distance leaves the ratios unchanged and
+        // keeps the largest weight at 1, so exp cannot underflow to 0/0.
+        let weight = |i: usize| {
+            ((col("__min_dist") - col(format!("cluster{i}dist").as_str())) / lit(temperature)).exp()
+        };
+        let total = (1..self.centers.len())
+            .map(weight)
+            .fold(weight(0), |acc, w| acc + w);
+
+        self.with_nearest_label(self.fitted_space(df), None)
+            .select(
+                (0..self.centers.len())
+                    .map(|i| (weight(i) / total.clone()).alias(&format!("cluster{i}prob")))
+                    .collect::<Vec<_>>(),
+            )
+            .collect()
+            .unwrap()
+    }
+
+    /// Centroid distances of `df`, already in fitted space, in contiguous
+    /// columns with missing distances as NaN, ready for
+    /// [`distance_slices`](Self::distance_slices).
+    fn distance_frame(&self, df: LazyFrame) -> DataFrame {
+        let mut distances = df
+            .select(
+                self.distance_exprs()
+                    .into_iter()
+                    .map(|e| e.fill_null(lit(f64::NAN)))
+                    .collect::<Vec<_>>(),
+            )
+            .collect()
+            .unwrap();
+        distances.as_single_chunk_par();
+
+        distances
+    }
+
+    /// Borrows every distance column of a
+    /// [`distance_frame`](Self::distance_frame) without copying it.
+    fn distance_slices(distances: &DataFrame) -> Vec<&[f64]> {
+        distances
+            .get_columns()
+            .iter()
+            .map(|s| s.f64().unwrap().cont_slice().unwrap())
+            .collect()
+    }
+
+    /// Index of the smallest distance per row, given one distance column
+    /// per centroid and the `n` of every row. Ties go to the lower centroid
+    /// index. Fails on a NaN distance, which would otherwise never compare
+    /// smaller.
+    fn nearest(
+        distances: &[Vec<f64>],
+        ids: &[u64],
+        ties: Option<(TieBreak, &[usize])>,
+    ) -> Result<Vec<u64>, Error> {
+        (0..ids.len())
+            .map(|i| {
+                let mut min_dist = f64::MAX;
+                let mut min_dist_idx = 0;
+
+                for (j, dist) in distances.iter().enumerate() {
+                    let dist = dist[i];
+                    if dist.is_nan() {
+                        return Err(Error::NanDistance { n: ids[i] });
+                    }
+
+                    let wins_tie = dist == min_dist
+                        && ties.is_some_and(|(tie_break, sizes)| {
+                            tie_break.prefers(sizes[j], sizes[min_dist_idx])
+                        });
+                    if dist < min_dist || wins_tie {
+                        min_dist = dist;
+                        min_dist_idx = j;
+                    }
+                }
+
+                Ok(min_dist_idx as u64)
+            })
+            .collect()
+    }
+
+    /// Cluster sizes from the previous step, one per current centroid, when
+    /// the [`TieBreak`] needs them. `None` on the first step and after a
+    /// coincident merge, where ties fall back to the lowest index.
+    fn tie_sizes(&self) -> Option<Vec<usize>> {
+        if self.tie_break == TieBreak::LowestIndex {
+            return None;
+        }
+
+        // Empty clusters lost their centroid in the update, so the
+        // remaining sizes line up with the current centroids.
+        let sizes = self
+            .size_history
+            .last()?
+            .iter()
+            .copied()
+            .filter(|&size| size > 0)
+            .collect::<Vec<_>>();
+
+        (sizes.len() == self.centers.len()).then_some(sizes)
+    }
+
+    /// Reverts every label whose new centroid is not closer than the
+    /// previous one by more than `margin`.
+    fn keep_within_margin(labels: &mut [u64], last: &[u64], distances: &[Vec<f64>], margin: f64) {
+        for (i, (label, last)) in labels.iter_mut().zip(last).enumerate() {
+            let new = distances[*label as usize][i];
+            let current = distances[*last as usize][i];
+            if new >= current - margin {
+                *label = *last;
+            }
+        }
+    }
+
+    fn distance_exprs(&self) -> Vec<Expr> {
+        let mut exprs = Vec::new();
+        for i in 0..self.centers.len() {
+            let expr = match &self.ranges {
+                Some(ranges) => {
+                    DistanceMetric::range_normalized_expr(&self.features, &self.centers[i], ranges)
+                }
+                None => self.metric.expr(&self.features, &self.centers[i]),
+            };
+            exprs.push(expr.alias(format!("cluster{}dist", i).as_str()));
+        }
+
+        exprs
+    }
+
+    /// The data with every feature divided by its range under
+    /// [`DistanceMetric::RangeNormalized`], otherwise unchanged.
+    fn range_normalized(&self) -> LazyFrame {
+        match &self.ranges {
+            Some(ranges) => self.df.clone().with_columns(
+                self.features
+                    .iter()
+                    .zip(ranges)
+                    .map(|(f, r)| col(f).cast(DataType::Float64) / lit(*r))
+                    .collect::<Vec<_>>(),
+            ),
+            None => self.df.clone(),
+        }
+    }
+
+    /// Fits an independent clustering for every distinct value of
+    /// `group_col`, running the groups in parallel. `n_clusters` applies to
+    /// each group on its own, capped at the group's row count. `center_ids`
+    /// seed only the groups containing them, with one cluster per id found
+    /// in the group; groups holding none of them fall back to the
+    /// configured init. Per-step output is not written for grouped runs.
+    /// Fails with the first group's error where
+    /// [`try_eval`](Self::try_eval) would.
+    pub fn eval_grouped(
+        self,
+        group_col: &str,
+    ) -> Result<BTreeMap<String, Vec<(u64, LazyFrame)>>, Error> {
+        let groups = self
+            .df
+            .clone()
+            .with_column(col(group_col).cast(DataType::Utf8))
+            .collect()
+            .unwrap()
+            .partition_by_stable([group_col], true)
+            .unwrap();
+
+        groups
+            .into_par_iter()
+            .map(|mut group| {
+                let key = group
+                    .drop_in_place(group_col)
+                    .unwrap()
+                    .utf8()
+                    .unwrap()
+                    .get(0)
+                    .unwrap_or("null")
+                    .to_owned();
+
+                let mut kmeans = self.clone();
+                if let Some(center_ids) = &self.center_ids {
+                    let ids = group
+                        .column("n")
+                        .unwrap()
+                        .u64()
+                        .unwrap()
+                        .into_no_null_iter()
+                        .collect::<HashSet<_>>();
+                    let present = center_ids
+                        .iter()
+                        .copied()
+                        .filter(|id| ids.contains(id))
+                        .collect::<Vec<_>>();
+
+                    if !present.is_empty() {
+                        kmeans.n_clusters = present.len();
+                    }
+                    kmeans.center_ids = (!present.is_empty()).then_some(present);
+                }
+                kmeans.n_clusters = kmeans.n_clusters.min(group.height());
+                kmeans.df = group.lazy();
+                kmeans.features = Self::feature_columns(&kmeans.df);
+                kmeans.write_distances = false;
+                kmeans.write_step_clusters = false;
+
+                Ok((key, kmeans.try_eval()?))
+            })
+            .collect()
+    }
+
+    /// Recomputes every centroid as its cluster mean. Clusters without rows
+    /// are dropped, as they are everywhere else, rather than yielding `NaN`
+    /// centroids.
+    fn eval_centers(&mut self) {
+        let mut clusters = Vec::new();
+        let mut centers = Vec::<Vec<f64>>::new();
+        let mut std_devs = Vec::new();
+        let stats = self.center_stats();
+
+        for (id, lf) in self.clusters.clone() {
+            let row = lf.clone().select(stats.clone()).collect().unwrap();
+            let row = row
+                .get(0)
+                .unwrap()
+                .into_iter()
+                .map(|v| v.try_extract::<f64>().unwrap_or(0.0))
+                .collect::<Vec<_>>();
+            if row[0] == 0.0 {
+                continue;
+            }
+
+            let center = match &self.center_update {
+                Some(update) => {
+                    let center = (update.lock().unwrap())(&points(&lf));
+                    assert_eq!(
+                        center.len(),
+                        self.features.len(),
+                        "centroid update returned {} coordinates for {} features",
+                        center.len(),
+                        self.features.len()
+                    );
+                    center
+                }
+                None => row[1..].iter().step_by(2).copied().collect(),
+            };
+            centers.push(center);
+            std_devs.push(row[2..].iter().step_by(2).copied().collect());
+            clusters.push((id, lf));
+        }
+
+        self.clusters = clusters;
+        self.centers = centers;
+        self.std_devs = std_devs;
+    }
+
+    /// Row count, then every feature's centroid coordinate and population
+    /// standard deviation, as one aggregation over a cluster.
+    fn center_stats(&self) -> Vec<Expr> {
+        let mut stats = vec![count().cast(DataType::Float64)];
+        stats.extend(Self::feature_columns(&self.df).iter().flat_map(|f| {
+            let x = col(f).cast(DataType::Float64);
+            [
+                self.centroid_update
+                    .expr(x.clone())
+                    .alias(&format!("{f}_mean")),
+                x.std(0).alias(&format!("{f}_std")),
+            ]
+        }));
+
+        stats
+    }
+
+    /// Orders centroids lexicographically and renumbers clusters to match.
+    fn sort_clusters(&mut self) {
+        let mut pairs = self
+            .centers
+            .drain(..)
+            .zip(self.std_devs.drain(..))
+            .zip(self.clusters.drain(..))
+            .collect::<Vec<_>>();
+        pairs.sort_by(|((a, _), _), ((b, _), _)| native::lexicographic(a, b));
+
+        for (i, ((center, std_dev), (_, lf))) in pairs.into_iter().enumerate() {
+            self.centers.push(center);
+            self.std_devs.push(std_dev);
+            self.clusters.push((i as u64, lf));
+        }
+    }
+
+    fn is_constant(&self) -> bool {
+        self.feature_bounds()
+            .iter()
+            .all(|(_, min, max)| max - min == 0.0)
+    }
+
+    fn feature_columns(df: &LazyFrame) -> Vec<String> {
+        df.schema()
+            .unwrap()
+            .iter_names()
+            .filter(|name| name.as_str() != "n")
+            .map(|name| name.to_string())
+            .collect()
+    }
+
+    fn rng(&self) -> StdRng {
+        match self.seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        }
+    }
+
+    /// Initial centroids per the configured method. Fails on a seed with a
+    /// NaN or missing feature, which no row could ever be assigned to.
+    fn init_centers(&self) -> Result<Vec<Vec<f64>>, Error> {
+        let centers = self.seed_centers();
+        if let Some(seed) = centers.iter().position(|c| c.iter().any(|x| x.is_nan())) {
+            return Err(Error::NanSeed { seed });
+        }
+
+        Ok(centers)
+    }
+
+    fn seed_centers(&self) -> Vec<Vec<f64>> {
+        if let Some(centers) = &self.initial_centers {
+            return centers.clone();
+        }
+
+        let df = self.df.clone();
+        let n_clusters = self.n_clusters;
+
+        let center_ids = match (&self.center_ids, self.init) {
+            (Some(center_ids), _) => center_ids.iter().copied().collect::<Series>(),
+            (None, InitMethod::KMeansPlusPlus { power }) => {
+                // Seeding runs on range-normalised copies so that it sees the
+                // same distances as the iterations.
+                let seeding = self
+                    .ranges
+                    .as_ref()
+                    .map(|_| points(&self.range_normalized()));
+                let points = points(&df);
+                let mut rng = self.rng();
+                let seeds = kmeans_plus_plus(
+                    seeding.as_ref().unwrap_or(&points),
+                    n_clusters,
+                    power,
+                    self.min_seed_separation,
+                    self.metric,
+                    &mut rng,
+                );
+
+                return seeds.into_iter().map(|i| points[i].clone()).collect();
+            }
+            (None, InitMethod::Quantile) => {
+                let levels = quantile_levels(n_clusters);
+                let exprs = levels
+                    .iter()
+                    .enumerate()
+                    .flat_map(|(j, q)| {
+                        self.features.iter().map(move |f| {
+                            col(f)
+                                .quantile(lit(*q), QuantileInterpolOptions::Linear)
+                                .alias(format!("{f}_q{j}").as_str())
+                        })
+                    })
+                    .collect::<Vec<_>>();
+
+                let row = df.select(exprs).collect().unwrap();
+                let row = row
+                    .get(0)
+                    .unwrap()
+                    .into_iter()
+                    .map(|v| v.try_extract::<f64>().unwrap())
+                    .collect::<Vec<_>>();
+
+                return row
+                    .chunks(self.features.len())
+                    .map(|center| center.to_vec())
+                    .collect();
+            }
+            (None, InitMethod::Random) => {
+                let ids = df.clone().select([col("n")]).collect().unwrap();
+                let ids = ids.column("n").unwrap().u64().unwrap();
+
+                let mut rng = self.rng();
+                let mut center_ids = HashSet::new();
+
+                while center_ids.len() < n_clusters.min(ids.len()) {
+                    center_ids.insert(ids.get(rng.gen_range(0..ids.len())).unwrap());
+                }
+
+                center_ids.into_iter().collect()
+            }
+        };
+
+        let centers_df = df
+            .clone()
+            .filter(col("n").is_in(lit(center_ids)))
+            .select(&[col("*").exclude(["n"])])
+            .collect()
+            .unwrap();
+
+        let mut centers = Vec::<Vec<f64>>::new();
+        for i in 0..centers_df.height() {
+            centers.push(Vec::new());
+
+            for j in centers_df.get(i).unwrap() {
+                centers[i].push(j.try_extract::<f64>().unwrap_or(f64::NAN));
+            }
+        }
+
+        centers
+    }
+}
+
+/// Configuration and fit state only; the frames are never printed or
+/// collected.
+impl fmt::Debug for KMeans {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("KMeans")
+            .field("n_clusters", &self.n_clusters)
+            .field("features", &self.features)
+            .field("transposed", &self.point_names.is_some())
+            .field("metric", &self.metric)
+            .field("init", &self.init)
+            .field("min_seed_separation", &self.min_seed_separation)
+            .field("seed", &self.seed)
+            .field("n_init", &self.n_init)
+            .field("iterations", &self.iterations)
+            .field("converged", &self.converged)
+            .field("label_change_tol", &self.label_change_tol)
+            .field("fit_sample_frac", &self.fit_sample_frac)
+            .field("tie_break", &self.tie_break)
+            .field("constraints", &self.constraints.is_some())
+            .field("coincident", &self.coincident)
+            .field("sparse", &self.sparse)
+            .field("algorithm", &self.algorithm)
+            .field("metrics_distance", &self.metrics_distance)
+            .field("centroid_update", &self.centroid_update)
+            .field("center_update", &self.center_update.is_some())
+            .field("convergence", &self.convergence.is_some())
+            .field("shuffle", &self.shuffle)
+            .field("validation_frac", &self.validation_frac)
+            .field("unassigned", &self.unassigned.len())
+            .field("scaled", &self.scaler.is_some())
+            .field("pca", &self.pca.as_ref().map(|pca| pca.components().len()))
+            .field("write_distances", &self.write_distances)
+            .field("write_step_clusters", &self.write_step_clusters)
+            .field("write_step_centroids", &self.write_step_centroids)
+            .field("max_output_files", &self.max_output_files)
+            .field("fitted_centers", &self.centers.len())
+            .finish_non_exhaustive()
+    }
+}
+
+impl fmt::Display for KMeans {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "KMeans(k={}, metric={}, init={}, n_init={}, tol={}, centers={})",
+            self.n_clusters,
+            self.metric,
+            self.init,
+            self.n_init,
+            self.label_change_tol,
+            self.centers.len()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{init::quantile_centers, into_labels, make_blobs};
+
+    fn kmeans(df: LazyFrame, k: usize) -> KMeans {
+        KMeans::new(df, k, None, false, CsvWriterOptions::default())
+            .unwrap()
+            .with_seed(1)
+    }
+
+    fn frame(columns: Vec<Series>) -> LazyFrame {
+        let n = Series::new("n", (0..columns[0].len() as u64).collect::<Vec<_>>());
+        DataFrame::new([vec![n], columns].concat()).unwrap().lazy()
+    }
+
+    #[test]
+    fn date_feature_is_rejected() {
+        let day = Series::new("day", [0i32, 1, 2, 3])
+            .cast(&DataType::Date)
+            .unwrap();
+        let x = Series::new("x", [0.0, 1.0, 5.0, 6.0]);
+        let result = kmeans(frame(vec![x, day]), 2).try_fit();
+
+        assert_eq!(
+            result.err(),
+            Some(Error::NonNumericFeature {
+                column: "day".to_owned(),
+                dtype: "date".to_owned(),
+            })
+        );
+    }
+
+    #[test]
+    fn boolean_exclude_mask_is_not_a_feature() {
+        let x = Series::new("x", [0.0, 1.0, 5.0, 6.0, 100.0]);
+        let noise = Series::new("noise", [false, false, false, false, true]);
+        let result = kmeans(frame(vec![x, noise]), 2)
+            .with_exclude_mask("noise")
+            .try_fit()
+            .unwrap();
+
+        assert_eq!(result.centers, vec![vec![0.5], vec![5.5]]);
+    }
+
+    #[test]
+    fn text_group_column_is_not_a_feature() {
+        let x = Series::new("x", [0.0, 1.0, 5.0, 6.0]);
+        let group = Series::new("group", ["a", "a", "b", "b"]);
+        let groups = kmeans(frame(vec![x, group]), 1)
+            .eval_grouped("group")
+            .unwrap();
+
+        assert_eq!(groups.keys().collect::<Vec<_>>(), ["a", "b"]);
+    }
+
+    fn with_missing(x: Series) -> LazyFrame {
+        let y = Series::new("y", [0.0, 1.0, 2.0, 8.0, 9.0]);
+        frame(vec![x, y])
+    }
+
+    /// Rows indexed `10, 20, ..` so that errors must report `n` rather
+    /// than the row position. Row `n = 20` has a missing `x`.
+    fn with_missing_at_20(x: [Option<f64>; 5], center_ids: Vec<u64>) -> KMeans {
+        let df = df!(
+            "n" => [10u64, 20, 30, 40, 50],
+            "x" => x,
+            "y" => [0.0, 1.0, 2.0, 8.0, 9.0]
+        )
+        .unwrap()
+        .lazy();
+
+        KMeans::new(df, 2usize, Some(center_ids), false, Default::default()).unwrap()
+    }
+
+    #[test]
+    fn null_feature_is_an_error_with_every_assignment() {
+        let x = [Some(0.0), None, Some(1.0), Some(8.0), Some(9.0)];
+        let algorithms = [Algorithm::Lloyd, Algorithm::Elkan];
+
+        for kmeans in algorithms
+            .map(|a| with_missing_at_20(x, vec![10, 40]).with_algorithm(a))
+            .into_iter()
+            .chain([with_missing_at_20(x, vec![10, 40]).with_switch_margin(0.1)])
+        {
+            assert_eq!(kmeans.try_fit().err(), Some(Error::NanDistance { n: 20 }));
+        }
+    }
+
+    #[test]
+    fn injected_nan_is_an_error() {
+        let x = [Some(0.0), Some(f64::NAN), Some(1.0), Some(8.0), Some(9.0)];
+        let result = with_missing_at_20(x, vec![10, 40])
+            .with_algorithm(Algorithm::Lloyd)
+            .try_fit();
+
+        assert_eq!(result.err(), Some(Error::NanDistance { n: 20 }));
+    }
+
+    #[test]
+    fn null_seed_is_an_error() {
+        let x = [Some(0.0), None, Some(1.0), Some(8.0), Some(9.0)];
+        let result = with_missing_at_20(x, vec![20, 40]).try_fit();
+
+        assert_eq!(result.err(), Some(Error::NanSeed { seed: 0 }));
+    }
+
+    #[test]
+    fn null_feature_is_left_unassigned() {
+        let x = Series::new("x", [Some(0.0), None, Some(1.0), Some(8.0), Some(9.0)]);
+        let mut kmeans = kmeans(with_missing(x), 2).with_nan_policy(NanPolicy::Unassigned);
+        let labels = into_labels(&kmeans.eval(), "n");
+
+        assert_eq!(kmeans.unassigned(), [1]);
+        assert_eq!(
+            labels.iter().map(|(n, _)| *n).collect::<Vec<_>>(),
+            [0, 2, 3, 4]
+        );
+    }
+
+    #[test]
+    fn constraints_hold() {
+        let (df, _) = make_blobs(100, 2, 2, 0.5, 4);
+        let must = vec![(0, 99), (1, 98)];
+        let cannot = vec![(2, 3), (0, 1)];
+        let result = kmeans(df, 2)
+            .with_constraints(must.clone(), cannot.clone())
+            .unwrap()
+            .fit();
+        let labels = into_labels(&result.clusters, "n");
+
+        assert!(must
+            .iter()
+            .all(|&(a, b)| labels[a as usize].1 == labels[b as usize].1));
+        assert!(cannot
+            .iter()
+            .all(|&(a, b)| labels[a as usize].1 != labels[b as usize].1));
+    }
+
+    #[test]
+    fn infeasible_constraints_are_an_error() {
+        let (df, _) = make_blobs(30, 2, 2, 0.5, 4);
+        let result = kmeans(df, 2)
+            .with_constraints(Vec::new(), vec![(1, 2), (2, 3), (1, 3)])
+            .unwrap()
+            .try_fit();
+
+        assert!(matches!(
+            result.err(),
+            Some(Error::InfeasibleConstraints { n_clusters: 2, .. })
+        ));
+    }
+
+    #[test]
+    fn output_file_cap_is_an_error() {
+        let (df, _) = make_blobs(30, 2, 2, 0.5, 4);
+        let result = kmeans(df, 2)
+            .with_write_distances(true)
+            .with_max_output_files(0)
+            .try_fit();
+
+        assert_eq!(
+            result.err(),
+            Some(Error::TooManyOutputFiles {
+                step: 1,
+                max_output_files: 0,
+            })
+        );
+    }
+
+    #[test]
+    fn confidence_of_pca_projected_clusters() {
+        let (df, _) = make_blobs(200, 4, 3, 0.5, 7);
+        let mut kmeans = kmeans(df, 3).with_max_dims(2, 2);
+
+        for (_, lf) in kmeans.eval() {
+            let confidence = kmeans.add_confidence(lf).collect().unwrap();
+            let confidence = confidence.column("confidence").unwrap().f64().unwrap();
+            assert!(confidence
+                .into_no_null_iter()
+                .all(|c| (0.0..=1.0).contains(&c)));
+        }
+    }
+
+    #[test]
+    fn kl_divergence_groups_simplex_points() {
+        let a = Series::new("a", [0.9, 0.85, 0.8, 0.1, 0.05, 0.15, 0.05, 0.1]);
+        let b = Series::new("b", [0.05, 0.1, 0.1, 0.8, 0.9, 0.8, 0.05, 0.1]);
+        let c = Series::new("c", [0.05, 0.05, 0.1, 0.1, 0.05, 0.05, 0.9, 0.8]);
+        let result = kmeans(frame(vec![a, b, c]), 3)
+            .with_metric(DistanceMetric::KLDivergence)
+            .fit();
+
+        let labels = into_labels(&result.clusters, "n")
+            .into_iter()
+            .map(|(_, cluster)| cluster)
+            .collect::<Vec<_>>();
+        assert_eq!(labels[0..3], [labels[0]; 3]);
+        assert_eq!(labels[3..6], [labels[3]; 3]);
+        assert_eq!(labels[6..8], [labels[6]; 2]);
+        assert!(labels[0] != labels[3] && labels[3] != labels[6] && labels[0] != labels[6]);
+    }
+
+    #[test]
+    fn identical_rows_form_a_single_cluster() {
+        for &init in InitMethod::all() {
+            let x = Series::new("x", [3.0; 6]);
+            let y = Series::new("y", [-1.0; 6]);
+            let mut kmeans = kmeans(frame(vec![x, y]), 3).with_init(init);
+            let clusters = kmeans.eval();
+
+            assert_eq!(clusters.len(), 1);
+            assert_eq!(kmeans.centers, vec![vec![3.0, -1.0]]);
+            assert!(kmeans.converged());
+        }
+    }
+
+    #[test]
+    fn seeded_fits_have_a_stable_centroid_order() {
+        let fit = || {
+            let (df, _) = make_blobs(200, 2, 4, 1.0, 3);
+            kmeans(df, 4).with_seed(9).fit()
+        };
+        let (first, second) = (fit(), fit());
+
+        assert_eq!(first.centers, second.centers);
+        assert!(first.centers.windows(2).all(|pair| pair[0] <= pair[1]));
+        assert_eq!(
+            into_labels(&first.clusters, "n"),
+            into_labels(&second.clusters, "n")
+        );
+        for (id, lf) in &first.clusters {
+            let mean = lf.clone().select([col("x0").mean()]).collect().unwrap();
+            let mean = mean.column("x0").unwrap().f64().unwrap().get(0).unwrap();
+            assert!((mean - first.centers[*id as usize][0]).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn quantile_init_needs_no_seed() {
+        let (df, _) = make_blobs(200, 3, 4, 1.0, 5);
+        let seeds = || {
+            KMeans::new(df.clone(), 4usize, None, false, CsvWriterOptions::default())
+                .unwrap()
+                .with_init(InitMethod::Quantile)
+                .init_centers()
+                .unwrap()
+        };
+
+        assert_eq!(seeds(), seeds());
+        assert_eq!(seeds(), quantile_centers(&points(&df), 4));
+    }
+
+    #[test]
+    fn empty_cluster_is_dropped_without_nan() {
+        let x = Series::new("x", [0.0, 1.0, 5.0, 6.0]);
+        let df = frame(vec![x]);
+        let mut kmeans = kmeans(df.clone(), 3);
+        kmeans.clusters = vec![
+            (0, df.clone().filter(col("x").lt(lit(3.0)))),
+            (1, df.clone().filter(lit(false))),
+            (2, df.filter(col("x").gt(lit(3.0)))),
+        ];
+        kmeans.eval_centers();
+
+        assert_eq!(kmeans.centers, vec![vec![0.5], vec![5.5]]);
+        assert_eq!(
+            kmeans
+                .clusters
+                .iter()
+                .map(|(id, _)| *id)
+                .collect::<Vec<_>>(),
+            [0, 2]
+        );
+    }
+
+    #[test]
+    fn unreachable_initial_center_leaves_no_nan() {
+        let x = Series::new("x", [0.0, 1.0, 5.0, 6.0]);
+        let result = kmeans(frame(vec![x]), 3)
+            .with_initial_centers(vec![vec![0.0], vec![6.0], vec![1e9]])
+            .fit();
+
+        assert_eq!(result.centers, vec![vec![0.5], vec![5.5]]);
+        assert_eq!(result.requested_k, 3);
+    }
+
+    /// Symmetric data whose mirror-invariant centroid update puts both
+    /// halves' centroids on the same coordinates after the first step.
+    fn mirrored(policy: CoincidentPolicy) -> FitResult {
+        let x = Series::new("x", [-2.0, -1.0, 1.0, 2.0]);
+        kmeans(frame(vec![x]), 2)
+            .with_initial_centers(vec![vec![-1.5], vec![1.5]])
+            .with_center_update(|points| {
+                vec![points.iter().map(|p| p[0].abs()).sum::<f64>() / points.len() as f64]
+            })
+            .with_coincident_policy(policy)
+            .fit()
+    }
+
+    #[test]
+    fn coincident_centroids_merge_on_symmetric_data() {
+        let result = mirrored(CoincidentPolicy::Merge);
+
+        assert_eq!(result.centers, vec![vec![1.5]]);
+        assert_eq!(into_labels(&result.clusters, "n").len(), 4);
+    }
+
+    #[test]
+    fn coincident_centroids_perturb_on_symmetric_data() {
+        let result = mirrored(CoincidentPolicy::Perturb);
+
+        assert_eq!(result.centers.len(), 2);
+        assert_ne!(result.centers[0], result.centers[1]);
+    }
+
+    #[test]
+    fn converges_when_the_partition_is_stable() {
+        let x = Series::new("x", [0.0, 1.0, 2.0, 50.0, 51.0, 52.0]);
+        let mut kmeans = kmeans(frame(vec![x]), 2).with_initial_centers(vec![vec![0.0], vec![1.0]]);
+        kmeans.eval();
+
+        assert!(kmeans.converged());
+        // The partition settles in the second step and the third confirms it.
+        assert_eq!(kmeans.iterations(), 3);
+        assert_eq!(kmeans.centers, vec![vec![1.0], vec![51.0]]);
+    }
+
+    /// Contents of the files in `dir` starting with `prefix`, keyed by the
+    /// rest of their name. The files are removed.
+    fn take_outputs(dir: &std::path::Path, prefix: &str) -> BTreeMap<String, Vec<u8>> {
+        std::fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter_map(|path| {
+                let name = path.file_name()?.to_str()?.strip_prefix(prefix)?.to_owned();
+                let contents = std::fs::read(&path).unwrap();
+                std::fs::remove_file(&path).unwrap();
+                Some((name, contents))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn written_clusters_are_byte_stable() {
+        let dir = std::env::temp_dir().join(format!("kmeans_stable_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let names = OutputNames::default();
+        // The derived default has a batch size of 0, on which sinks spin.
+        let csv_options = CsvWriterOptions {
+            has_header: true,
+            batch_size: 1000,
+            ..Default::default()
+        };
+        let run = |prefix: &str| {
+            let (df, _) = make_blobs(300, 2, 3, 1.5, 8);
+            let mut kmeans = KMeans::new(df, 3usize, None, false, csv_options.clone())
+                .unwrap()
+                .with_seed(1)
+                .with_output_dir(dir.to_str().unwrap())
+                .with_output_prefix(prefix)
+                .with_write_step_clusters(true);
+            for (i, lf) in kmeans.eval() {
+                lf.sink_csv(dir.join(names.result(prefix, i)), csv_options.clone())
+                    .unwrap();
+            }
+
+            take_outputs(&dir, prefix)
+        };
+        let first = run("a_");
+        let second = run("b_");
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert!(first.contains_key(&names.result("", 0)));
+        assert!(first.contains_key(&names.step_cluster("", 1, 0)));
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn trimmed_mean_resists_outliers() {
+        let center = |update: CentroidUpdate, outliers: &[f64]| {
+            let x = [
+                &[1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0, 9.0, 10.0],
+                outliers,
+            ]
+            .concat();
+            kmeans(frame(vec![Series::new("x", x)]), 1)
+                .with_centroid_update(update)
+                .fit()
+                .centers[0][0]
+        };
+        let shift = |update| (center(update, &[100.0, 120.0]) - center(update, &[])).abs();
+        let trimmed = CentroidUpdate::TrimmedMean { fraction: 0.2 };
+
+        assert!(shift(trimmed) < shift(CentroidUpdate::Mean) / 10.0);
+    }
+
+    #[test]
+    #[should_panic(expected = "trimmed mean fraction must be in [0, 0.5)")]
+    fn trimmed_mean_rejects_half() {
+        let x = Series::new("x", [0.0, 1.0]);
+        kmeans(frame(vec![x]), 1)
+            .with_centroid_update(CentroidUpdate::TrimmedMean { fraction: 0.5 });
+    }
+
+    #[test]
+    fn labels_follow_a_shuffled_input_order() {
+        let df = df!(
+            "n" => [7u64, 2, 9, 0, 5, 3],
+            "x" => [50.0, 0.0, 51.0, 1.0, 52.0, 2.0]
+        )
+        .unwrap()
+        .lazy();
+        let mut kmeans = kmeans(df.clone(), 2);
+        kmeans.eval();
+        let labelled = kmeans.add_labels(df).collect().unwrap();
+
+        assert_eq!(
+            labelled
+                .column("n")
+                .unwrap()
+                .u64()
+                .unwrap()
+                .into_no_null_iter()
+                .collect::<Vec<_>>(),
+            [7, 2, 9, 0, 5, 3]
+        );
+        assert_eq!(
+            labelled
+                .column("cluster")
+                .unwrap()
+                .u64()
+                .unwrap()
+                .into_no_null_iter()
+                .collect::<Vec<_>>(),
+            [1, 0, 1, 0, 1, 0]
+        );
+    }
+
+    /// 300 rows holding only three distinct points.
+    fn duplicates() -> LazyFrame {
+        let x = (0..300).map(|i| (i % 3) as f64 * 10.0).collect::<Vec<_>>();
+        frame(vec![Series::new("x", x)])
+    }
+
+    #[test]
+    fn duplicates_reduce_k_to_the_distinct_count() {
+        let result = kmeans(duplicates(), 5)
+            .with_distinct_policy(DistinctPolicy::Reduce)
+            .unwrap()
+            .fit();
+
+        assert_eq!(result.centers, vec![vec![0.0], vec![10.0], vec![20.0]]);
+        assert_eq!(result.centers.len(), 3);
+        assert_eq!(result.n_clusters_requested(), 5);
+    }
+
+    #[test]
+    fn duplicates_are_an_error_by_policy() {
+        let too_few = Some(Error::TooFewDistinctPoints { k: 5, distinct: 3 });
+
+        assert_eq!(
+            kmeans(duplicates(), 5)
+                .with_distinct_policy(DistinctPolicy::Error)
+                .err(),
+            too_few
+        );
+        assert_eq!(
+            KMeans::new(
+                duplicates(),
+                5usize,
+                Some(vec![0, 1, 2, 3, 4]),
+                false,
+                Default::default()
+            )
+            .unwrap()
+            .with_distinct_policy(DistinctPolicy::Reduce)
+            .err(),
+            too_few
+        );
+    }
+
+    #[test]
+    fn center_ids_seed_only_their_own_group() {
+        let x = Series::new("x", [0.0, 1.0, 5.0, 6.0, 20.0, 21.0, 30.0, 31.0]);
+        let group = Series::new("group", ["a", "a", "a", "a", "b", "b", "b", "b"]);
+        let groups = KMeans::new(
+            frame(vec![x, group]),
+            2usize,
+            Some(vec![0, 2]),
+            false,
+            Default::default(),
+        )
+        .unwrap()
+        .with_seed(1)
+        .eval_grouped("group")
+        .unwrap();
+
+        let ids = |key: &str| {
+            into_labels(&groups[key], "n")
+                .into_iter()
+                .map(|(_, cluster)| cluster)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(ids("a"), [0, 0, 1, 1]);
+        assert_eq!(ids("b").len(), 4);
+    }
+
+    #[test]
+    fn fit_lazy_converges_to_the_eager_fit() {
+        let (df, _) = make_blobs(300, 2, 3, 0.5, 12);
+        let eager = kmeans(df.clone(), 3).with_init(InitMethod::Quantile).fit();
+        let mut lazy = kmeans(df, 3).with_init(InitMethod::Quantile);
+        let labelled = lazy.fit_lazy().collect().unwrap();
+
+        assert!(lazy.converged());
+        assert!(lazy.iterations() < FIT_LAZY_MAX_STEPS);
+        assert_eq!(labelled.height(), 300);
+        assert_eq!(lazy.centers.len(), eager.centers.len());
+        for (a, b) in lazy.centers.iter().zip(&eager.centers) {
+            assert!(a.iter().zip(b).all(|(x, y)| (x - y).abs() < 1e-9));
+        }
+    }
+
+    #[test]
+    fn shuffling_keeps_labels_on_their_rows() {
+        let (df, _) = make_blobs(120, 2, 3, 0.5, 13);
+        let fit = |shuffle| {
+            kmeans(df.clone(), 3)
+                .with_init(InitMethod::Quantile)
+                .with_shuffle(shuffle)
+                .fit()
+        };
+        let shuffled = fit(true);
+
+        assert_eq!(
+            into_labels(&shuffled.clusters, "n"),
+            into_labels(&fit(false).clusters, "n")
+        );
+        for (_, lf) in &shuffled.clusters {
+            let n = lf.clone().collect().unwrap();
+            let n = n.column("n").unwrap().u64().unwrap();
+            assert!(n.into_no_null_iter().is_sorted());
+        }
+    }
+
+    #[test]
+    fn display_uses_the_cli_spelling_of_metric_and_init() {
+        let x = Series::new("x", [0.0, 1.0, 5.0]);
+        let kmeans = kmeans(frame(vec![x]), 2).with_init(InitMethod::kmeans_plus_plus());
+
+        let shown = kmeans.to_string();
+        assert!(
+            shown.starts_with("KMeans(k=2, metric=euclidean, init=kmeans++, "),
+            "{shown}"
+        );
+    }
+}
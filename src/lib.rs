@@ -0,0 +1,88 @@
+#[cfg(feature = "full")]
+mod analysis;
+#[cfg(feature = "full")]
+mod constraints;
+#[cfg(feature = "full")]
+mod datasets;
+mod distance;
+mod error;
+#[cfg(feature = "full")]
+mod export;
+mod hull;
+mod init;
+#[cfg(feature = "full")]
+mod input;
+#[cfg(feature = "full")]
+mod intrinsic;
+#[cfg(feature = "full")]
+mod kmeans;
+mod metrics;
+pub mod native;
+mod output;
+#[cfg(feature = "full")]
+mod pca;
+#[cfg(feature = "full")]
+mod scaling;
+#[cfg(feature = "full")]
+mod selection;
+#[cfg(feature = "full")]
+mod sparse;
+#[cfg(feature = "full")]
+mod xmeans;
+
+#[cfg(feature = "full")]
+pub use analysis::{
+    cluster_hull_measures, feature_contributions, feature_importance, into_labels,
+    merge_close_clusters, merge_close_clusters_with, nearest_neighbor_distances, split_cluster,
+    MergeCriterion,
+};
+#[cfg(feature = "full")]
+pub use datasets::make_blobs;
+#[cfg(feature = "simd")]
+pub use distance::squared_euclidean_simd;
+pub use distance::{squared_euclidean, squared_euclidean_scalar, DistanceMetric};
+pub use error::Error;
+#[cfg(feature = "ndarray")]
+pub use export::to_ndarray;
+#[cfg(feature = "full")]
+pub use export::write_geojson;
+pub use hull::{hull_area, hull_measure, hull_volume};
+pub use init::InitMethod;
+#[cfg(feature = "full")]
+pub use input::CsvInput;
+#[cfg(feature = "full")]
+pub use intrinsic::estimate_intrinsic_dim;
+#[cfg(feature = "full")]
+pub use kmeans::{
+    CentroidUpdate, ConvergenceState, DistinctPolicy, FitResult, KMeans, NanPolicy,
+    RadiusAssignment,
+};
+#[cfg(feature = "full")]
+pub use metrics::dann_index;
+pub use metrics::{
+    adjusted_rand_index, balance, davies_bouldin, dunn, dunn_index_sampled, dunn_with_linkage,
+    inertia, silhouette, Linkage, MetricsBundle, SelectionMetric,
+};
+pub use native::{Algorithm, CoincidentPolicy, TieBreak};
+pub use output::OutputNames;
+#[cfg(feature = "full")]
+pub use pca::Pca;
+#[cfg(feature = "full")]
+pub use scaling::{Scaler, Scaling};
+#[cfg(feature = "full")]
+pub use selection::{find_knee, select_k_by_metric, sweep_k};
+#[cfg(feature = "full")]
+pub use xmeans::xmeans;
+
+pub const RESULT_DIRECTORY: &str = "./result";
+
+/// Caps the number of threads used by Polars and by the crate's own Rayon
+/// work. Polars sizes its pool on first use, so this must be called before
+/// any Polars operation; later calls have no effect on an existing pool.
+#[cfg(feature = "full")]
+pub fn set_max_threads(max_threads: usize) {
+    std::env::set_var("POLARS_MAX_THREADS", max_threads.to_string());
+    let _ = rayon::ThreadPoolBuilder::new()
+        .num_threads(max_threads)
+        .build_global();
+}
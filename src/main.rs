@@ -1,26 +1,86 @@
-use std::collections::HashSet;
+use std::time::{SystemTime, UNIX_EPOCH};
 
+use kmeans::{
+    find_knee, sweep_k, CsvInput, KMeans, OutputNames, SelectionMetric, RESULT_DIRECTORY,
+};
+#[cfg(feature = "plotting")]
 use plotters::prelude::*;
 use polars::prelude::*;
-use rand::Rng;
 
-const RESULT_DIRECTORY: &str = "./result";
+fn arg_value(name: &str) -> Option<String> {
+    std::env::args().skip_while(|arg| arg != name).nth(1)
+}
 
 fn main() {
-    if std::path::Path::new(&RESULT_DIRECTORY).exists() {
+    if let Some(max_threads) = arg_value("--max-threads") {
+        kmeans::set_max_threads(
+            max_threads
+                .parse()
+                .expect("--max-threads expects an integer"),
+        );
+    }
+
+    let append = std::env::args().any(|arg| arg == "--append");
+    let confidence = std::env::args().any(|arg| arg == "--confidence");
+    let warm_start = std::env::args().any(|arg| arg == "--warm-start");
+    let elbow = std::env::args().any(|arg| arg == "--elbow");
+    // Single-linkage Dunn is quadratic in the number of rows; centroid
+    // linkage keeps the sweep fast on large inputs.
+    let selection_metric = if std::env::args().any(|arg| arg == "--centroid-linkage") {
+        SelectionMetric::DunnCentroid
+    } else {
+        SelectionMetric::Dunn
+    };
+    let exclude_mask = arg_value("--exclude-mask");
+    let centers_file = arg_value("--init-centers-file");
+    let mut output_names = OutputNames::default();
+    if let Some(result_name) = arg_value("--result-name") {
+        output_names.result = result_name;
+    }
+    let write_distances = !std::env::args().any(|arg| arg == "--no-distance-files");
+    let write_step_centroids = std::env::args().any(|arg| arg == "--step-centroids");
+    let parquet = arg_value("--parquet");
+    let max_dims = arg_value("--max-dims").map(|max_dims| {
+        max_dims
+            .parse::<usize>()
+            .expect("--max-dims expects an integer")
+    });
+    let auto_index = !std::env::args().any(|arg| arg == "--no-auto-index");
+    let index_as_feature = std::env::args().any(|arg| arg == "--index-as-feature");
+    let validation_frac = arg_value("--validation-frac").map(|frac| {
+        frac.parse::<f64>()
+            .expect("--validation-frac expects a number")
+    });
+    let integer_columns = arg_value("--integer-columns")
+        .map(|columns| columns.split(',').map(str::to_owned).collect::<Vec<_>>())
+        .unwrap_or_default();
+    let seed =
+        arg_value("--seed").map(|seed| seed.parse::<u64>().expect("--seed expects an integer"));
+
+    let run_prefix = if append {
+        // Milliseconds plus the process id, so runs started within the same
+        // second, or concurrently, do not overwrite each other's files.
+        let millis = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_millis();
+        format!("{millis}_{}_", std::process::id())
+    } else {
+        String::new()
+    };
+
+    if !append && std::path::Path::new(&RESULT_DIRECTORY).exists() {
         std::fs::remove_dir_all(RESULT_DIRECTORY).expect("Failed to remove result directory");
     }
 
     std::fs::create_dir_all(RESULT_DIRECTORY).expect("Failed to create result directory");
 
-    let df = LazyCsvReader::new("kmeans.csv")
-        .has_header(true)
-        .finish()
-        .unwrap()
-        .select([
-            col("n").cast(DataType::UInt64),
-            col("*").exclude(["n"]).cast(DataType::Float64),
-        ]);
+    let df = CsvInput::new("kmeans.csv")
+        .with_auto_index(auto_index)
+        .with_index_as_feature(index_as_feature)
+        .with_integer_columns(&integer_columns)
+        .load()
+        .unwrap();
 
     let csv_options = CsvWriterOptions {
         has_header: true,
@@ -39,37 +99,107 @@ fn main() {
         },
     };
 
-    let mut dann_indexes = Vec::new();
-    for i in 2..=15_u8 {
-        let kmeans = KMeans::new(df.clone(), i, None, false, csv_options.clone());
-        let clusters = kmeans.eval();
-        if clusters.len() != i as usize {
-            println!("{} num of clusters decreased to {}", i, clusters.len());
-        } else {
-            let dann_index = dann_index(clusters.clone());
-            dann_indexes.push((i, dann_index));
+    let sweep_kmeans = |k: usize| {
+        let mut kmeans = KMeans::new(df.clone(), k, None, false, csv_options.clone()).unwrap();
+        if let Some(mask) = &exclude_mask {
+            kmeans = kmeans.with_exclude_mask(mask);
+        }
+        if let Some(max_dims) = max_dims {
+            kmeans = kmeans.with_max_dims(max_dims, max_dims);
+        }
+        if let Some(frac) = validation_frac {
+            kmeans = kmeans.with_validation_frac(frac);
         }
+        match seed {
+            Some(seed) => kmeans.with_seed(seed + k as u64),
+            None => kmeans,
+        }
+    };
+
+    // Every k is fitted independently, each with its own seed derived from
+    // `--seed` so the sweep is reproducible regardless of scheduling, unless
+    // `--warm-start` chains each k onto the previous fit. A centers file
+    // fixes k, so there is nothing to sweep.
+    let (best_k, dunn_indexes) = match &centers_file {
+        Some(path) => {
+            let centers = CsvInput::new(path).load().unwrap().collect().unwrap();
+            (centers.height(), Vec::new())
+        }
+        None => sweep_k(2..=15, selection_metric, warm_start, sweep_kmeans),
+    };
+
+    // The elbow is read off a separate inertia sweep, reported alongside the
+    // selected k rather than replacing it.
+    if elbow && centers_file.is_none() {
+        let (_, scores) = sweep_k(2..=15, SelectionMetric::Inertia, warm_start, sweep_kmeans);
+        let inertias = scores.iter().map(|(k, s)| (*k, -s)).collect::<Vec<_>>();
+        println!("elbow at k = {}", find_knee(&inertias));
     }
 
-    let mut max = (0_u8, f64::MIN);
-    dann_indexes.iter().for_each(|(x, y)| if *y > max.1 { max = (*x, *y) });
+    let mut kmeans = KMeans::new(df.clone(), best_k, None, true, csv_options.clone())
+        .unwrap()
+        .with_output_prefix(&run_prefix)
+        .with_write_distances(write_distances)
+        .with_write_step_centroids(write_step_centroids)
+        .with_selection(selection_metric)
+        .with_output_names(output_names.clone());
+    if let Some(mask) = &exclude_mask {
+        kmeans = kmeans.with_exclude_mask(mask);
+    }
+    if let Some(max_dims) = max_dims {
+        kmeans = kmeans.with_max_dims(max_dims, max_dims);
+    }
+    if let Some(seed) = seed {
+        kmeans = kmeans.with_seed(seed + best_k as u64);
+    }
+    if let Some(path) = &centers_file {
+        kmeans = kmeans.with_centers_file(path).unwrap();
+    }
 
-    let kmeans = KMeans::new(df.clone(), max.0, None, true, csv_options.clone());
+    for (i, lf) in kmeans.eval() {
+        let lf = if confidence {
+            kmeans.add_confidence(lf)
+        } else {
+            lf
+        };
 
-    for (i, lf) in kmeans.eval().iter().enumerate() {
-        let _ = lf.clone().sink_csv(
-            format!("{RESULT_DIRECTORY}/res_{}_cluster.csv", i).into(),
+        let _ = lf.sink_csv(
+            format!("{RESULT_DIRECTORY}/{}", output_names.result(&run_prefix, i)).into(),
             csv_options.clone(),
         );
     }
-    
-    let chart_path = format!("{RESULT_DIRECTORY}/dann_index.png");
+
+    kmeans
+        .write_manifest("kmeans.csv")
+        .expect("Failed to write manifest");
+
+    if let Some(parquet) = &parquet {
+        kmeans
+            .sink_parquet(format!("{RESULT_DIRECTORY}/{parquet}"))
+            .expect("Failed to write parquet output");
+    }
+
+    if centers_file.is_some() {
+        return;
+    }
+
+    #[cfg(feature = "plotting")]
+    plot_dunn_indexes(&dunn_indexes, &output_names.chart(&run_prefix));
+    #[cfg(not(feature = "plotting"))]
+    for (k, dunn_index) in &dunn_indexes {
+        println!("{k}\t{dunn_index}");
+    }
+}
+
+#[cfg(feature = "plotting")]
+fn plot_dunn_indexes(dunn_indexes: &[(usize, f64)], chart_name: &str) {
+    let chart_path = format!("{RESULT_DIRECTORY}/{chart_name}");
     let root = BitMapBackend::new(&chart_path, (1024, 768)).into_drawing_area();
     root.fill(&WHITE).unwrap();
 
     let mut chart = ChartBuilder::on(&root)
         .margin(10)
-        .caption("Dann Index over Clusters", ("sans-serif", 40))
+        .caption("Dunn Index over Clusters", ("sans-serif", 40))
         .set_label_area_size(LabelAreaPosition::Left, 60)
         .set_label_area_size(LabelAreaPosition::Bottom, 40)
         .build_cartesian_2d(1..16, 0.0..1.0)
@@ -87,7 +217,7 @@ fn main() {
         .unwrap();
 
     let _ = chart.draw_series(LineSeries::new(
-        dann_indexes
+        dunn_indexes
             .iter()
             .map(|(x, y)| (*x as i32, *y))
             .collect::<Vec<_>>(),
@@ -95,297 +225,8 @@ fn main() {
     ));
 
     let _ = chart.draw_series(
-        dann_indexes
+        dunn_indexes
             .iter()
             .map(|(x, y)| Circle::new((*x as i32, *y), 3, BLUE.filled())),
     );
 }
-
-#[derive(Clone)]
-struct KMeans {
-    df: LazyFrame,
-    clusters: Vec<LazyFrame>,
-    centers: Vec<Vec<f64>>,
-    io: bool,
-    csv_options: CsvWriterOptions,
-}
-
-impl KMeans {
-    pub fn new(
-        df: LazyFrame,
-        n_clusters: impl Into<usize>,
-        center_ids: Option<Vec<u64>>,
-        io: bool,
-        csv_options: CsvWriterOptions,
-    ) -> Self {
-        let n_clusters = n_clusters.into();
-        let centers = Self::init_centers(df.clone(), n_clusters, center_ids);
-
-        KMeans {
-            df,
-            centers,
-            clusters: Vec::new(),
-            io,
-            csv_options,
-        }
-    }
-
-    fn eval(mut self) -> Vec<LazyFrame> {
-        let mut clusters_last;
-        self.clusters = vec![self.df.clone()];
-
-        let mut step = 1;
-
-        loop {
-            clusters_last = self.clusters.clone();
-
-            let mut exprs = Vec::new();
-            for i in 0..self.centers.len() {
-                exprs.push(
-                    ((col("x") - lit(self.centers[i][0])).pow(2)
-                        + (col("y") - lit(self.centers[i][1])).pow(2)
-                        + (col("z") - lit(self.centers[i][2])).pow(2))
-                    .sqrt()
-                    .alias(format!("cluster{}dist", i).as_str()),
-                );
-            }
-
-            let df_clusters = self.df.clone().with_columns(exprs);
-
-            if self.io {
-                let _ = df_clusters.clone().sink_csv(
-                    format!("{RESULT_DIRECTORY}/{}__dist.csv", step).into(),
-                    self.csv_options.clone(),
-                );
-            }
-
-            let df_num = df_clusters
-                .clone()
-                .select(&[col("n")])
-                .collect()
-                .unwrap()
-                .iter()
-                .map(|s| s.u64().unwrap().into_no_null_iter().collect::<Vec<_>>())
-                .collect::<Vec<_>>();
-
-            let df_num = df_num[0].clone();
-
-            let clusters_dist = df_clusters
-                .clone()
-                .select(&[col("*").exclude(["x", "y", "z", "n"])])
-                .collect()
-                .unwrap()
-                .iter()
-                .map(|s| s.f64().unwrap().into_no_null_iter().collect::<Vec<_>>())
-                .collect::<Vec<_>>();
-
-            let mut cluster_tags = Vec::new();
-
-            for i in 0..df_num.len() {
-                let mut min_dist = f64::MAX;
-                let mut min_dist_idx = 0;
-
-                for (j, dist) in clusters_dist.iter().enumerate() {
-                    let dist = dist[i];
-
-                    if dist < min_dist {
-                        min_dist = dist;
-                        min_dist_idx = j;
-                    }
-                }
-
-                cluster_tags.push(min_dist_idx as u64);
-            }
-
-            let s1 = Series::new("n", df_num);
-            let s2 = Series::new("cluster", cluster_tags);
-
-            let df_clusters = DataFrame::new(vec![s1, s2]).unwrap().lazy();
-
-            let df = self.df.clone().left_join(df_clusters, col("n"), col("n"));
-
-            self.clusters = df
-                .collect()
-                .unwrap()
-                .partition_by(["cluster"], false)
-                .unwrap()
-                .into_iter()
-                .map(|x| x.lazy())
-                .collect();
-
-            if self.io {
-                for (i, lf) in self.clusters.iter().enumerate() {
-                    let _ = lf.clone().sink_csv(
-                        format!("{RESULT_DIRECTORY}/{}_{}_cluster.csv", step, i).into(),
-                        self.csv_options.clone(),
-                    );
-                }
-            }
-
-            self.eval_centers();
-
-            step += 1;
-
-            if self.centers.len() <= 1 {
-                return self.clusters;
-            }
-
-            if self.clusters.len() != clusters_last.len() {
-                continue;
-            }
-
-            let mut count = 0;
-            for i in 0..self.clusters.len() {
-                for j in &clusters_last {
-                    if self.clusters[i]
-                        .clone()
-                        .collect()
-                        .unwrap()
-                        .eq(&j.clone().collect().unwrap())
-                    {
-                        count += 1;
-                    }
-                }
-            }
-
-            if count == self.clusters.len() {
-                return self.clusters;
-            }
-
-            if self.clusters[0]
-                .clone()
-                .collect()
-                .unwrap()
-                .eq(&clusters_last[0].clone().collect().unwrap())
-            {
-                return self.clusters;
-            }
-        }
-    }
-
-    fn eval_centers(&mut self) {
-        let mut centers = Vec::<Vec<f64>>::new();
-
-        for lf in self.clusters.clone() {
-            centers.push(
-                lf.select([col("*").exclude(["n"])])
-                    .collect()
-                    .unwrap()
-                    .iter()
-                    .map(|s| s.sum::<f64>().unwrap() / s.len() as f64)
-                    .collect(),
-            );
-        }
-
-        self.centers = centers;
-    }
-
-    fn init_centers(
-        df: LazyFrame,
-        n_clusters: usize,
-        center_ids: Option<Vec<u64>>,
-    ) -> Vec<Vec<f64>> {
-        let height = { df.clone().collect().unwrap().height() };
-
-        let center_ids = match center_ids {
-            Some(center_ids) => center_ids.into_iter().collect::<Series>(),
-            None => {
-                let mut rng = rand::thread_rng();
-                let mut center_ids = HashSet::new();
-
-                while center_ids.len() < n_clusters {
-                    center_ids.insert(rng.gen_range(0..height as u64));
-                }
-
-                center_ids.into_iter().collect()
-            }
-        };
-
-        let centers_df = df
-            .clone()
-            .filter(col("n").is_in(lit(center_ids)))
-            .select(&[col("*").exclude(["n"])])
-            .collect()
-            .unwrap();
-
-        let mut centers = Vec::<Vec<f64>>::new();
-        for i in 0..centers_df.height() {
-            centers.push(Vec::new());
-
-            for j in centers_df.get(i).unwrap() {
-                centers[i].push(j.try_extract::<f64>().unwrap());
-            }
-        }
-
-        centers
-    }
-}
-
-fn dann_index(lf: Vec<LazyFrame>) -> f64 {
-    let mut min = f64::MAX;
-    let mut max = f64::MIN;
-
-    for i in 0..lf.len() {
-        let lf1 = lf[i].clone();
-
-        let s = lf1
-            .clone()
-            .select([col("*").exclude(["n"])])
-            .collect()
-            .unwrap()
-            .iter()
-            .map(|s| s.f64().cloned().unwrap())
-            .collect::<Vec<_>>();
-
-        for j in 0..s[0].len() {
-            for k in 0..s[0].len() {
-                if j == k {
-                    continue;
-                }
-
-                let mut sum = 0.0;
-                for l in s.clone() {
-                    sum += (l.get(j).unwrap() - l.get(k).unwrap()).powi(2);
-                }
-
-                let d = sum.sqrt();
-
-                if d > max {
-                    max = d;
-                }
-            }
-        }
-
-        for (j, lf2) in lf.iter().enumerate() {
-            if i == j {
-                continue;
-            }
-
-            let s2 = lf2
-                .clone()
-                .select([col("*").exclude(["n"])])
-                .collect()
-                .unwrap()
-                .iter()
-                .map(|s| s.f64().cloned().unwrap())
-                .collect::<Vec<_>>();
-
-            for k in 0..s[0].len() {
-                for l in 0..s2[0].len() {
-                    let mut sum = 0.0;
-                    for m in 0..s.len() {
-                        sum += (s[m].get(k).unwrap() - s2[m].get(l).unwrap()).powi(2);
-                    }
-
-                    let d = sum.sqrt();
-
-                    if d < min {
-                        min = d;
-                    }
-                }
-            }
-        }
-    }
-
-    min / max
-}
@@ -0,0 +1,392 @@
+#[cfg(feature = "full")]
+use polars::prelude::*;
+
+use std::{collections::HashMap, fmt, str::FromStr};
+
+use rand::{rngs::StdRng, seq::index::sample, SeedableRng};
+
+use crate::{
+    distance::{squared_euclidean, DistanceMetric},
+    error::{from_name, Error},
+};
+
+/// Every quality metric of a clustering, computed from a single
+/// materialisation of the cluster frames.
+#[derive(Clone, Debug)]
+pub struct MetricsBundle {
+    pub dunn: f64,
+    pub silhouette: f64,
+    pub davies_bouldin: f64,
+    pub inertia: f64,
+    pub balance: f64,
+}
+
+impl MetricsBundle {
+    pub fn new(points: &[Vec<Vec<f64>>], centers: &[Vec<f64>]) -> Self {
+        Self::with_distance(points, centers, DistanceMetric::Euclidean)
+    }
+
+    /// Same as [`new`](Self::new) with every metric measured under
+    /// `distance`; inertia sums the divergences under
+    /// [`DistanceMetric::KLDivergence`].
+    pub fn with_distance(
+        points: &[Vec<Vec<f64>>],
+        centers: &[Vec<f64>],
+        distance: DistanceMetric,
+    ) -> Self {
+        MetricsBundle {
+            dunn: dunn_by(points, Linkage::Single, distance),
+            silhouette: silhouette_by(points, distance),
+            davies_bouldin: davies_bouldin_by(points, centers, distance),
+            inertia: inertia_by(points, centers, distance),
+            balance: balance(points),
+        }
+    }
+}
+
+/// Quality metric used to compare clusterings of the same data.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SelectionMetric {
+    Inertia,
+    Silhouette,
+    Dunn,
+    /// [`Dunn`](Self::Dunn) with [`Linkage::Centroid`], cheap enough for
+    /// large sweeps.
+    DunnCentroid,
+}
+
+/// How [`dunn_with_linkage`] measures cluster separation and size.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Linkage {
+    /// Closest pair of points across clusters over the widest pair within
+    /// one. Exact but quadratic in the number of points and driven by
+    /// outliers.
+    #[default]
+    Single,
+    /// Closest pair of centroids over twice the largest point-to-centroid
+    /// distance. Linear in the points plus quadratic in k.
+    Centroid,
+}
+
+impl SelectionMetric {
+    /// Every metric, e.g. to list them in a UI.
+    pub const fn all() -> &'static [SelectionMetric] {
+        &[
+            SelectionMetric::Inertia,
+            SelectionMetric::Silhouette,
+            SelectionMetric::Dunn,
+            SelectionMetric::DunnCentroid,
+        ]
+    }
+
+    /// Score oriented so that higher is better for every metric.
+    pub fn score(&self, points: &[Vec<Vec<f64>>], centers: &[Vec<f64>]) -> f64 {
+        self.score_with_distance(points, centers, DistanceMetric::Euclidean)
+    }
+
+    /// [`score`](Self::score) with distances measured under `distance`.
+    pub fn score_with_distance(
+        &self,
+        points: &[Vec<Vec<f64>>],
+        centers: &[Vec<f64>],
+        distance: DistanceMetric,
+    ) -> f64 {
+        match self {
+            SelectionMetric::Inertia => -inertia_by(points, centers, distance),
+            SelectionMetric::Silhouette => silhouette_by(points, distance),
+            SelectionMetric::Dunn => dunn_by(points, Linkage::Single, distance),
+            SelectionMetric::DunnCentroid => dunn_by(points, Linkage::Centroid, distance),
+        }
+    }
+}
+
+impl fmt::Display for SelectionMetric {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            SelectionMetric::Inertia => "inertia",
+            SelectionMetric::Silhouette => "silhouette",
+            SelectionMetric::Dunn => "dunn",
+            SelectionMetric::DunnCentroid => "dunn-centroid",
+        })
+    }
+}
+
+/// Parses the [`Display`](fmt::Display) name, ignoring case.
+impl FromStr for SelectionMetric {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        from_name("selection metric", Self::all(), s)
+    }
+}
+
+/// Normalised entropy of the cluster sizes: 1 when every cluster holds the
+/// same number of points, towards 0 as one cluster swallows the rest. A
+/// single cluster counts as balanced.
+pub fn balance(points: &[Vec<Vec<f64>>]) -> f64 {
+    let total = points.iter().map(Vec::len).sum::<usize>() as f64;
+    if points.len() < 2 || total == 0.0 {
+        return 1.0;
+    }
+
+    let entropy = points
+        .iter()
+        .filter(|cluster| !cluster.is_empty())
+        .map(|cluster| {
+            let p = cluster.len() as f64 / total;
+            -p * p.ln()
+        })
+        .sum::<f64>();
+
+    entropy / (points.len() as f64).ln()
+}
+
+/// Adjusted Rand index between two labelings of the same points: 1 for
+/// identical partitions up to renumbering, about 0 for independent ones.
+/// Two trivial partitions (all in one cluster or all apart) score 1.
+pub fn adjusted_rand_index(a: &[u64], b: &[u64]) -> f64 {
+    assert_eq!(a.len(), b.len(), "labelings must cover the same points");
+
+    let pairs = |count: usize| (count * count.saturating_sub(1)) as f64 / 2.0;
+
+    let mut contingency = HashMap::new();
+    let mut a_sizes = HashMap::new();
+    let mut b_sizes = HashMap::new();
+    for (x, y) in a.iter().zip(b) {
+        *contingency.entry((x, y)).or_insert(0) += 1;
+        *a_sizes.entry(x).or_insert(0) += 1;
+        *b_sizes.entry(y).or_insert(0) += 1;
+    }
+
+    let index = contingency.values().map(|c| pairs(*c)).sum::<f64>();
+    let a_pairs = a_sizes.values().map(|c| pairs(*c)).sum::<f64>();
+    let b_pairs = b_sizes.values().map(|c| pairs(*c)).sum::<f64>();
+    let expected = a_pairs * b_pairs / pairs(a.len()).max(1.0);
+    let max = (a_pairs + b_pairs) / 2.0;
+
+    if max == expected {
+        1.0
+    } else {
+        (index - expected) / (max - expected)
+    }
+}
+
+#[cfg(feature = "full")]
+pub fn dann_index(lf: Vec<LazyFrame>) -> f64 {
+    dunn(&lf.iter().map(points).collect::<Vec<_>>())
+}
+
+/// Collects a cluster frame's feature columns as one row per point,
+/// missing values as NaN.
+#[cfg(feature = "full")]
+pub(crate) fn points(lf: &LazyFrame) -> Vec<Vec<f64>> {
+    let df = lf
+        .clone()
+        .select([col("*").exclude(["n"]).cast(DataType::Float64)])
+        .collect()
+        .unwrap();
+
+    let columns = df
+        .iter()
+        .map(|s| {
+            s.f64()
+                .unwrap()
+                .into_iter()
+                .map(|x| x.unwrap_or(f64::NAN))
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+
+    (0..df.height())
+        .map(|i| columns.iter().map(|c| c[i]).collect())
+        .collect()
+}
+
+/// Smallest distance between points of different clusters over the largest
+/// distance between points of the same cluster.
+pub fn dunn(clusters: &[Vec<Vec<f64>>]) -> f64 {
+    dunn_by(clusters, Linkage::Single, DistanceMetric::Euclidean)
+}
+
+/// Dunn index with a choice of [`Linkage`]; [`Linkage::Single`] is the same
+/// as [`dunn`].
+pub fn dunn_with_linkage(clusters: &[Vec<Vec<f64>>], linkage: Linkage) -> f64 {
+    dunn_by(clusters, linkage, DistanceMetric::Euclidean)
+}
+
+/// Approximate [`dunn`] for large clusters: each cluster is first reduced
+/// to a seeded random sample of at most `max_points_per_cluster` points,
+/// bringing the cost down from quadratic in the rows to quadratic in
+/// `k × max_points_per_cluster`. Sampling can only miss the closest
+/// cross-cluster pair and the widest pair within a cluster, so the
+/// estimate never falls below the exact index; it equals [`dunn`] when no
+/// cluster exceeds the cap.
+pub fn dunn_index_sampled(
+    clusters: &[Vec<Vec<f64>>],
+    max_points_per_cluster: usize,
+    seed: u64,
+) -> f64 {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let sampled = clusters
+        .iter()
+        .map(|cluster| {
+            if cluster.len() <= max_points_per_cluster {
+                return cluster.clone();
+            }
+
+            sample(&mut rng, cluster.len(), max_points_per_cluster)
+                .into_iter()
+                .map(|i| cluster[i].clone())
+                .collect()
+        })
+        .collect::<Vec<_>>();
+
+    dunn(&sampled)
+}
+
+fn dunn_by(clusters: &[Vec<Vec<f64>>], linkage: Linkage, metric: DistanceMetric) -> f64 {
+    if linkage == Linkage::Centroid {
+        return centroid_dunn(clusters, metric);
+    }
+
+    let distance = |a: &[f64], b: &[f64]| metric.distance(a, b);
+    let mut min = f64::MAX;
+    let mut max = f64::MIN;
+
+    for (i, a) in clusters.iter().enumerate() {
+        for (j, p) in a.iter().enumerate() {
+            for q in &a[j + 1..] {
+                max = max.max(distance(p, q));
+            }
+        }
+
+        for b in &clusters[i + 1..] {
+            for p in a {
+                for q in b {
+                    min = min.min(distance(p, q));
+                }
+            }
+        }
+    }
+
+    min / max
+}
+
+fn centroid_dunn(clusters: &[Vec<Vec<f64>>], metric: DistanceMetric) -> f64 {
+    let distance = |a: &[f64], b: &[f64]| metric.distance(a, b);
+    let centroids = clusters
+        .iter()
+        .filter(|cluster| !cluster.is_empty())
+        .map(|cluster| {
+            let mut centroid = vec![0.0; cluster[0].len()];
+            for p in cluster {
+                for (c, x) in centroid.iter_mut().zip(p) {
+                    *c += x;
+                }
+            }
+            centroid.iter_mut().for_each(|c| *c /= cluster.len() as f64);
+            (cluster, centroid)
+        })
+        .collect::<Vec<_>>();
+
+    let mut min = f64::MAX;
+    let mut max = f64::MIN;
+
+    for (i, (cluster, a)) in centroids.iter().enumerate() {
+        for p in cluster.iter() {
+            max = max.max(2.0 * distance(p, a));
+        }
+
+        for (_, b) in &centroids[i + 1..] {
+            min = min.min(distance(a, b));
+        }
+    }
+
+    min / max
+}
+
+/// Mean silhouette coefficient over all points; points in singleton
+/// clusters score zero.
+pub fn silhouette(clusters: &[Vec<Vec<f64>>]) -> f64 {
+    silhouette_by(clusters, DistanceMetric::Euclidean)
+}
+
+fn silhouette_by(clusters: &[Vec<Vec<f64>>], metric: DistanceMetric) -> f64 {
+    let distance = |a: &[f64], b: &[f64]| metric.distance(a, b);
+    let mut sum = 0.0;
+    let mut count = 0;
+
+    for (i, a) in clusters.iter().enumerate() {
+        for p in a {
+            count += 1;
+
+            if a.len() < 2 {
+                continue;
+            }
+
+            let own = a.iter().map(|q| distance(p, q)).sum::<f64>() / (a.len() - 1) as f64;
+            let nearest = clusters
+                .iter()
+                .enumerate()
+                .filter(|(j, b)| *j != i && !b.is_empty())
+                .map(|(_, b)| b.iter().map(|q| distance(p, q)).sum::<f64>() / b.len() as f64)
+                .fold(f64::MAX, f64::min);
+
+            if nearest < f64::MAX {
+                sum += (nearest - own) / own.max(nearest);
+            }
+        }
+    }
+
+    sum / count as f64
+}
+
+/// Average over clusters of the worst ratio of summed scatter to centroid
+/// separation; lower is better.
+pub fn davies_bouldin(clusters: &[Vec<Vec<f64>>], centers: &[Vec<f64>]) -> f64 {
+    davies_bouldin_by(clusters, centers, DistanceMetric::Euclidean)
+}
+
+fn davies_bouldin_by(
+    clusters: &[Vec<Vec<f64>>],
+    centers: &[Vec<f64>],
+    metric: DistanceMetric,
+) -> f64 {
+    let distance = |a: &[f64], b: &[f64]| metric.distance(a, b);
+    let scatter = clusters
+        .iter()
+        .zip(centers)
+        .map(|(a, c)| a.iter().map(|p| distance(p, c)).sum::<f64>() / a.len() as f64)
+        .collect::<Vec<_>>();
+
+    let mut sum = 0.0;
+    for i in 0..centers.len() {
+        let mut worst = 0.0_f64;
+        for j in 0..centers.len() {
+            if i != j {
+                worst = worst.max((scatter[i] + scatter[j]) / distance(&centers[i], &centers[j]));
+            }
+        }
+        sum += worst;
+    }
+
+    sum / centers.len() as f64
+}
+
+/// Sum of squared distances from every point to its cluster centroid.
+pub fn inertia(clusters: &[Vec<Vec<f64>>], centers: &[Vec<f64>]) -> f64 {
+    inertia_by(clusters, centers, DistanceMetric::Euclidean)
+}
+
+fn inertia_by(clusters: &[Vec<Vec<f64>>], centers: &[Vec<f64>], metric: DistanceMetric) -> f64 {
+    let cost = |p: &[f64], c: &[f64]| match metric {
+        DistanceMetric::Euclidean | DistanceMetric::RangeNormalized => squared_euclidean(p, c),
+        DistanceMetric::KLDivergence => metric.distance(p, c),
+    };
+
+    clusters
+        .iter()
+        .zip(centers)
+        .map(|(a, c)| a.iter().map(|p| cost(p, c)).sum::<f64>())
+        .sum()
+}
@@ -0,0 +1,542 @@
+//! Polars-free k-means over in-memory points, available without the `full`
+//! feature. Given the same data, seed and options it reaches the same
+//! clustering as [`KMeans`](crate::KMeans): both share initialisation,
+//! nearest-centroid assignment and the convergence rule defined here.
+
+use std::collections::{HashMap, HashSet};
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::{
+    init::{kmeans_plus_plus, quantile_centers},
+    squared_euclidean, DistanceMetric, Error, InitMethod,
+};
+
+/// Labels per point, numbered like the centroids they refer to.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Clustering {
+    pub labels: Vec<u64>,
+    pub centers: Vec<Vec<f64>>,
+}
+
+/// What happens when two centroids end an update at identical coordinates,
+/// which otherwise leaves them tied with the split decided by index.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum CoincidentPolicy {
+    /// Keep only the first of the coincident centroids, reducing `k`.
+    #[default]
+    Merge,
+    /// Nudge the later centroid by a tiny random offset so both keep
+    /// competing for points.
+    Perturb,
+}
+
+/// Which centroid takes a point that is exactly as far from two of them.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum TieBreak {
+    /// The centroid with the lower index.
+    #[default]
+    LowestIndex,
+    /// The centroid whose cluster held more points after the previous
+    /// step, falling back to the lower index on equal sizes.
+    LargerCluster,
+    /// The centroid whose cluster held fewer points after the previous
+    /// step, which evens out cluster sizes.
+    SmallerCluster,
+}
+
+impl TieBreak {
+    /// Whether a tied centroid whose cluster has `size` points beats the
+    /// current choice, whose cluster has `current`.
+    #[cfg(feature = "full")]
+    pub(crate) fn prefers(self, size: usize, current: usize) -> bool {
+        match self {
+            TieBreak::LowestIndex => false,
+            TieBreak::LargerCluster => size > current,
+            TieBreak::SmallerCluster => size < current,
+        }
+    }
+}
+
+/// Clusters `points` (one `Vec` of features per row) into at most
+/// `n_clusters` clusters. Clusters left empty during the iterations, or
+/// whose centroid coincides with another one, are dropped, and the final
+/// clusters are numbered by their centroid in lexicographic coordinate
+/// order. Fails with [`Error::EmptyInput`] when there are no points.
+pub fn fit(
+    points: &[Vec<f64>],
+    n_clusters: usize,
+    init: InitMethod,
+    metric: DistanceMetric,
+    seed: Option<u64>,
+) -> Result<Clustering, Error> {
+    if points.is_empty() {
+        return Err(Error::EmptyInput);
+    }
+    if points.iter().all(|p| *p == points[0]) {
+        return Ok(Clustering {
+            labels: vec![0; points.len()],
+            centers: vec![points[0].clone()],
+        });
+    }
+
+    let mut rng = match seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    };
+
+    let mut centers = match init {
+        InitMethod::Random => {
+            let mut seeds = HashSet::new();
+            while seeds.len() < n_clusters.min(points.len()) {
+                seeds.insert(rng.gen_range(0..points.len()));
+            }
+
+            let mut seeds = seeds.into_iter().collect::<Vec<_>>();
+            seeds.sort_unstable();
+            seeds.into_iter().map(|i| points[i].clone()).collect()
+        }
+        InitMethod::KMeansPlusPlus { power } => {
+            kmeans_plus_plus(points, n_clusters, power, 0.0, metric, &mut rng)
+                .into_iter()
+                .map(|i| points[i].clone())
+                .collect::<Vec<_>>()
+        }
+        InitMethod::Quantile => quantile_centers(points, n_clusters),
+    };
+
+    let mut labels_last: Option<Vec<u64>> = None;
+    let mut labels;
+
+    loop {
+        labels = assign(points, &centers, metric);
+
+        let changed = changed_fraction(&labels, labels_last.as_deref());
+        let converged = converged(&labels, labels_last.as_deref());
+        labels_last = Some(labels.clone());
+
+        (labels, centers) = update_centers(points, &labels);
+
+        if resolve_coincident(&mut centers, CoincidentPolicy::Merge, &mut rng) {
+            continue;
+        }
+
+        if centers.len() <= 1 || changed <= 0.0 || converged {
+            break;
+        }
+    }
+
+    Ok(sort_clusters(labels, centers))
+}
+
+/// Applies `policy` to every centroid equal to an earlier one and reports
+/// whether there was any, in which case the assignment must be redone.
+pub(crate) fn resolve_coincident(
+    centers: &mut Vec<Vec<f64>>,
+    policy: CoincidentPolicy,
+    rng: &mut StdRng,
+) -> bool {
+    let mut found = false;
+    let mut j = 1;
+    while j < centers.len() {
+        if !centers[..j].contains(&centers[j]) {
+            j += 1;
+            continue;
+        }
+
+        found = true;
+        match policy {
+            CoincidentPolicy::Merge => {
+                centers.remove(j);
+            }
+            CoincidentPolicy::Perturb => {
+                for c in &mut centers[j] {
+                    *c += 1e-6 * (c.abs() + 1.0) * rng.gen_range(-1.0..1.0);
+                }
+            }
+        }
+    }
+
+    found
+}
+
+/// Index of the nearest centroid for every point, the lowest index winning
+/// ties. Panics on a NaN distance.
+pub fn assign(points: &[Vec<f64>], centers: &[Vec<f64>], metric: DistanceMetric) -> Vec<u64> {
+    points
+        .iter()
+        .map(|p| {
+            let mut min_dist = f64::MAX;
+            let mut min_dist_idx = 0;
+
+            for (j, c) in centers.iter().enumerate() {
+                let dist = metric.distance(p, c);
+                assert!(!dist.is_nan(), "NaN distance to centroid {j}");
+
+                if dist < min_dist {
+                    min_dist = dist;
+                    min_dist_idx = j;
+                }
+            }
+
+            min_dist_idx as u64
+        })
+        .collect()
+}
+
+/// How points are matched to their nearest centroid each iteration.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Algorithm {
+    /// Compute every point-to-centroid distance.
+    Lloyd,
+    /// Skip distances ruled out by the triangle inequality, see [`Elkan`].
+    /// Gives the same labels as `Lloyd`; only applies to
+    /// [`DistanceMetric::Euclidean`].
+    Elkan,
+    /// Let `KMeans` pick from the shape of the data: `Elkan` for Euclidean
+    /// distance in at most 32 dimensions while its `n × k` bounds stay
+    /// under 25 million entries (about 200 MB), `Lloyd` otherwise or when
+    /// per-step distance files, sparse distances or a switch margin are
+    /// requested. Elkan was faster than the Polars Lloyd loop on every
+    /// benchmarked shape up to 16 dimensions.
+    #[default]
+    Auto,
+}
+
+// Relative slack on the bounds so rounding can never prune the true
+// nearest centroid.
+const BOUND_SLACK: f64 = 1e-12;
+
+/// Elkan's exact nearest-centroid assignment. Per point it keeps an upper
+/// bound on the distance to its centroid and a lower bound to every other
+/// centroid, moved by how far the centroids shift between calls, and skips
+/// every distance the bounds and the inter-centroid distances rule out.
+/// Labels match [`assign`] under Euclidean distance, ties included.
+#[derive(Clone, Debug)]
+pub struct Elkan {
+    points: Vec<Vec<f64>>,
+    labels: Vec<u64>,
+    upper: Vec<f64>,
+    lower: Vec<Vec<f64>>,
+    centers: Vec<Vec<f64>>,
+    evaluations: usize,
+}
+
+impl Elkan {
+    pub fn new(points: Vec<Vec<f64>>) -> Self {
+        Elkan {
+            points,
+            labels: Vec::new(),
+            upper: Vec::new(),
+            lower: Vec::new(),
+            centers: Vec::new(),
+            evaluations: 0,
+        }
+    }
+
+    /// The points being assigned, in the order labels are returned.
+    pub fn points(&self) -> &[Vec<f64>] {
+        &self.points
+    }
+
+    /// Point-to-centroid distances computed so far.
+    pub fn evaluations(&self) -> usize {
+        self.evaluations
+    }
+
+    /// Assigns every point to its nearest centroid in `centers`. The bounds
+    /// carry over from the previous call when the number of centroids is
+    /// unchanged, centroid `j` being taken as the moved previous `j`.
+    pub fn assign(&mut self, centers: &[Vec<f64>]) -> Vec<u64> {
+        let distance = |a: &[f64], b: &[f64]| squared_euclidean(a, b).sqrt();
+
+        if centers.len() != self.centers.len() || self.labels.is_empty() {
+            self.lower = self
+                .points
+                .iter()
+                .map(|p| centers.iter().map(|c| distance(p, c)).collect())
+                .collect();
+            self.evaluations += self.points.len() * centers.len();
+            self.labels = assign_from_distances(&self.lower);
+            self.upper = self
+                .labels
+                .iter()
+                .zip(&self.lower)
+                .map(|(a, l)| l[*a as usize])
+                .collect();
+            self.centers = centers.to_vec();
+
+            return self.labels.clone();
+        }
+
+        let shift = centers
+            .iter()
+            .zip(&self.centers)
+            .map(|(c, p)| distance(c, p))
+            .collect::<Vec<_>>();
+        let between = centers
+            .iter()
+            .map(|a| centers.iter().map(|b| distance(a, b)).collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        let half_gap = (0..centers.len())
+            .map(|j| {
+                (0..centers.len())
+                    .filter(|&o| o != j)
+                    .map(|o| between[j][o])
+                    .fold(f64::MAX, f64::min)
+                    / 2.0
+            })
+            .collect::<Vec<_>>();
+
+        for i in 0..self.points.len() {
+            let mut a = self.labels[i] as usize;
+            let mut upper = (self.upper[i] + shift[a]) * (1.0 + BOUND_SLACK);
+            for (l, s) in self.lower[i].iter_mut().zip(&shift) {
+                *l = ((*l - s) * (1.0 - BOUND_SLACK)).max(0.0);
+            }
+
+            if upper < half_gap[a] {
+                self.upper[i] = upper;
+                continue;
+            }
+
+            let mut tight = false;
+            for j in 0..centers.len() {
+                if j == a || self.pruned(i, j, a, upper, between[a][j]) {
+                    continue;
+                }
+
+                if !tight {
+                    upper = distance(&self.points[i], &centers[a]);
+                    self.lower[i][a] = upper;
+                    self.evaluations += 1;
+                    tight = true;
+
+                    if self.pruned(i, j, a, upper, between[a][j]) {
+                        continue;
+                    }
+                }
+
+                let dist = distance(&self.points[i], &centers[j]);
+                self.lower[i][j] = dist;
+                self.evaluations += 1;
+
+                if dist < upper || (dist == upper && j < a) {
+                    a = j;
+                    upper = dist;
+                }
+            }
+
+            self.labels[i] = a as u64;
+            self.upper[i] = upper;
+        }
+
+        self.centers = centers.to_vec();
+
+        self.labels.clone()
+    }
+
+    /// Whether centroid `j` provably cannot beat the current centroid `a`,
+    /// which wins ties when it has the lower index.
+    fn pruned(&self, i: usize, j: usize, a: usize, upper: f64, between: f64) -> bool {
+        let bound = self.lower[i][j].max(between / 2.0);
+        if j > a {
+            bound >= upper
+        } else {
+            bound > upper
+        }
+    }
+}
+
+/// Lowest-index argmin of every row of `distances`.
+fn assign_from_distances(distances: &[Vec<f64>]) -> Vec<u64> {
+    distances
+        .iter()
+        .map(|row| {
+            let mut min_dist = f64::MAX;
+            let mut min_dist_idx = 0;
+            for (j, dist) in row.iter().enumerate() {
+                assert!(!dist.is_nan(), "NaN distance to centroid {j}");
+                if *dist < min_dist {
+                    min_dist = *dist;
+                    min_dist_idx = j;
+                }
+            }
+
+            min_dist_idx as u64
+        })
+        .collect()
+}
+
+/// Mean of every non-empty cluster, with labels renumbered so that they
+/// index the returned centroids in ascending order of the original label.
+pub fn update_centers(points: &[Vec<f64>], labels: &[u64]) -> (Vec<u64>, Vec<Vec<f64>>) {
+    let mut ids = labels.to_vec();
+    ids.sort_unstable();
+    ids.dedup();
+
+    let index = ids
+        .iter()
+        .enumerate()
+        .map(|(i, id)| (*id, i as u64))
+        .collect::<HashMap<_, _>>();
+    let labels = labels.iter().map(|l| index[l]).collect::<Vec<_>>();
+
+    let mut sums = vec![vec![0.0; points[0].len()]; ids.len()];
+    let mut counts = vec![0; ids.len()];
+    for (p, l) in points.iter().zip(&labels) {
+        counts[*l as usize] += 1;
+        for (s, x) in sums[*l as usize].iter_mut().zip(p) {
+            *s += x;
+        }
+    }
+
+    let centers = sums
+        .into_iter()
+        .zip(counts)
+        .map(|(s, c)| s.into_iter().map(|x| x / c as f64).collect())
+        .collect();
+
+    (labels, centers)
+}
+
+/// Share of points whose label differs from the previous iteration; 1.0
+/// when there is no previous iteration.
+pub(crate) fn changed_fraction(labels: &[u64], labels_last: Option<&[u64]>) -> f64 {
+    changed_count(labels, labels_last) as f64 / labels.len() as f64
+}
+
+/// Number of points whose label differs from the previous iteration; every
+/// point when there is no previous iteration.
+pub(crate) fn changed_count(labels: &[u64], labels_last: Option<&[u64]>) -> usize {
+    match labels_last {
+        Some(labels_last) => labels
+            .iter()
+            .zip(labels_last)
+            .filter(|(a, b)| a != b)
+            .count(),
+        None => labels.len(),
+    }
+}
+
+/// Whether the partition stopped changing: every cluster keeps the same
+/// members as in the previous iteration, whatever its label. Only labels are
+/// compared, never centroid coordinates, so there is no floating-point
+/// tolerance involved.
+pub(crate) fn converged(labels: &[u64], labels_last: Option<&[u64]>) -> bool {
+    let Some(labels_last) = labels_last else {
+        return false;
+    };
+
+    let count = |labels: &[u64]| labels.iter().collect::<HashSet<_>>().len();
+    if count(labels) != count(labels_last) {
+        return false;
+    }
+
+    let mut mapping = HashMap::new();
+    labels
+        .iter()
+        .zip(labels_last)
+        .all(|(a, b)| *mapping.entry(a).or_insert(b) == b)
+}
+
+/// Orders centroids lexicographically and renumbers labels to match.
+pub(crate) fn sort_clusters(labels: Vec<u64>, centers: Vec<Vec<f64>>) -> Clustering {
+    let mut order = (0..centers.len()).collect::<Vec<_>>();
+    order.sort_by(|a, b| lexicographic(&centers[*a], &centers[*b]));
+
+    let mut rank = vec![0; centers.len()];
+    for (new, old) in order.iter().enumerate() {
+        rank[*old] = new as u64;
+    }
+
+    Clustering {
+        labels: labels.into_iter().map(|l| rank[l as usize]).collect(),
+        centers: order.into_iter().map(|i| centers[i].clone()).collect(),
+    }
+}
+
+pub(crate) fn lexicographic(a: &[f64], b: &[f64]) -> std::cmp::Ordering {
+    a.iter()
+        .zip(b)
+        .map(|(x, y)| x.total_cmp(y))
+        .find(|o| o.is_ne())
+        .unwrap_or(std::cmp::Ordering::Equal)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_input_is_an_error() {
+        let result = fit(
+            &[],
+            2,
+            InitMethod::Random,
+            DistanceMetric::Euclidean,
+            Some(1),
+        );
+
+        assert_eq!(result, Err(Error::EmptyInput));
+    }
+
+    #[test]
+    fn coincident_centroids_are_merged_or_perturbed() {
+        let symmetric = vec![vec![1.0, -1.0], vec![-1.0, 1.0], vec![1.0, -1.0]];
+        let mut rng = StdRng::seed_from_u64(1);
+
+        let mut merged = symmetric.clone();
+        assert!(resolve_coincident(
+            &mut merged,
+            CoincidentPolicy::Merge,
+            &mut rng
+        ));
+        assert_eq!(merged, symmetric[..2]);
+
+        let mut perturbed = symmetric.clone();
+        assert!(resolve_coincident(
+            &mut perturbed,
+            CoincidentPolicy::Perturb,
+            &mut rng
+        ));
+        assert_eq!(perturbed[..2], symmetric[..2]);
+        assert_ne!(perturbed[2], symmetric[2]);
+        assert!(!resolve_coincident(
+            &mut perturbed,
+            CoincidentPolicy::Merge,
+            &mut rng
+        ));
+    }
+
+    #[cfg(feature = "full")]
+    #[test]
+    fn matches_the_polars_path() {
+        use polars::prelude::*;
+
+        let (df, _) = crate::make_blobs(300, 3, 4, 1.0, 11);
+        let points = crate::metrics::points(&df);
+        let native = fit(
+            &points,
+            4,
+            InitMethod::Quantile,
+            DistanceMetric::Euclidean,
+            None,
+        );
+        let polars = crate::KMeans::new(df, 4usize, None, false, CsvWriterOptions::default())
+            .unwrap()
+            .with_init(InitMethod::Quantile)
+            .fit();
+
+        let labels = crate::into_labels(&polars.clusters, "n");
+        let native = native.unwrap();
+        assert_eq!(native.centers.len(), polars.centers.len());
+        assert_eq!(
+            native.labels,
+            labels.iter().map(|(_, l)| *l).collect::<Vec<_>>()
+        );
+        for (a, b) in native.centers.iter().zip(&polars.centers) {
+            assert!(a.iter().zip(b).all(|(x, y)| (x - y).abs() < 1e-9));
+        }
+    }
+}
@@ -0,0 +1,72 @@
+/// File name templates for everything written to the output directory,
+/// [`RESULT_DIRECTORY`](crate::RESULT_DIRECTORY) unless set with
+/// [`KMeans::with_output_dir`](crate::KMeans::with_output_dir). `{run}` is replaced by the
+/// run prefix (see [`KMeans::with_output_prefix`](crate::KMeans)), `{step}`
+/// by the iteration number and `{cluster}` by the cluster id.
+#[derive(Clone, Debug, PartialEq)]
+pub struct OutputNames {
+    /// Per-iteration distances of every row to each centroid.
+    pub distances: String,
+    /// Per-iteration rows of one cluster.
+    pub step_cluster: String,
+    /// Per-iteration centroid coordinates, one row per cluster.
+    pub step_centroids: String,
+    /// Final rows of one cluster.
+    pub result: String,
+    /// Chart of the k sweep written by the CLI.
+    pub chart: String,
+    /// Configuration and outcome of the run, see
+    /// [`KMeans::write_manifest`](crate::KMeans::write_manifest).
+    pub manifest: String,
+}
+
+impl Default for OutputNames {
+    fn default() -> Self {
+        OutputNames {
+            distances: "{run}{step}_dist.csv".to_owned(),
+            step_cluster: "{run}{step}_{cluster}_cluster.csv".to_owned(),
+            step_centroids: "{run}{step}_centroids.csv".to_owned(),
+            result: "{run}res_{cluster}_cluster.csv".to_owned(),
+            chart: "{run}dann_index.png".to_owned(),
+            manifest: "{run}manifest.json".to_owned(),
+        }
+    }
+}
+
+impl OutputNames {
+    pub fn distances(&self, run: &str, step: usize) -> String {
+        fill(&self.distances, run, Some(step), None)
+    }
+
+    pub fn step_cluster(&self, run: &str, step: usize, cluster: u64) -> String {
+        fill(&self.step_cluster, run, Some(step), Some(cluster))
+    }
+
+    pub fn step_centroids(&self, run: &str, step: usize) -> String {
+        fill(&self.step_centroids, run, Some(step), None)
+    }
+
+    pub fn result(&self, run: &str, cluster: u64) -> String {
+        fill(&self.result, run, None, Some(cluster))
+    }
+
+    pub fn chart(&self, run: &str) -> String {
+        fill(&self.chart, run, None, None)
+    }
+
+    pub fn manifest(&self, run: &str) -> String {
+        fill(&self.manifest, run, None, None)
+    }
+}
+
+fn fill(template: &str, run: &str, step: Option<usize>, cluster: Option<u64>) -> String {
+    let mut name = template.replace("{run}", run);
+    if let Some(step) = step {
+        name = name.replace("{step}", &step.to_string());
+    }
+    if let Some(cluster) = cluster {
+        name = name.replace("{cluster}", &cluster.to_string());
+    }
+
+    name
+}
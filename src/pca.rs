@@ -0,0 +1,103 @@
+use nalgebra::{DMatrix, SymmetricEigen};
+use polars::prelude::*;
+
+/// Principal component projection fitted on the feature columns of a frame.
+///
+/// The covariance matrix is eigen-decomposed with `nalgebra` and the
+/// components are kept in order of decreasing explained variance.
+#[derive(Clone, Debug)]
+pub struct Pca {
+    columns: Vec<String>,
+    mean: Vec<f64>,
+    components: Vec<Vec<f64>>,
+    explained_variance_ratio: Vec<f64>,
+}
+
+impl Pca {
+    pub fn fit(df: LazyFrame, columns: &[String], n_components: usize) -> Self {
+        let series = df
+            .select(
+                columns
+                    .iter()
+                    .map(|c| col(c).cast(DataType::Float64))
+                    .collect::<Vec<_>>(),
+            )
+            .collect()
+            .unwrap()
+            .iter()
+            .map(|s| s.f64().unwrap().into_no_null_iter().collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+
+        let height = series[0].len();
+        let mean = series
+            .iter()
+            .map(|s| s.iter().sum::<f64>() / height as f64)
+            .collect::<Vec<_>>();
+
+        let centered = DMatrix::from_fn(height, columns.len(), |i, j| series[j][i] - mean[j]);
+        let covariance = centered.transpose() * &centered / (height.max(2) - 1) as f64;
+
+        let eigen = SymmetricEigen::new(covariance);
+        let mut order = (0..eigen.eigenvalues.len()).collect::<Vec<_>>();
+        order.sort_by(|a, b| eigen.eigenvalues[*b].total_cmp(&eigen.eigenvalues[*a]));
+
+        let total = eigen.eigenvalues.iter().map(|v| v.max(0.0)).sum::<f64>();
+        let n_components = n_components.min(columns.len());
+
+        let components = order[..n_components]
+            .iter()
+            .map(|i| eigen.eigenvectors.column(*i).iter().copied().collect())
+            .collect();
+        let explained_variance_ratio = order[..n_components]
+            .iter()
+            .map(|i| {
+                if total > 0.0 {
+                    eigen.eigenvalues[*i].max(0.0) / total
+                } else {
+                    0.0
+                }
+            })
+            .collect();
+
+        Pca {
+            columns: columns.to_vec(),
+            mean,
+            components,
+            explained_variance_ratio,
+        }
+    }
+
+    /// Replaces the fitted feature columns with `pc0`, `pc1`, ... keeping `n`.
+    pub fn transform(&self, df: LazyFrame) -> LazyFrame {
+        let mut exprs = vec![col("n")];
+        exprs.extend(self.exprs());
+
+        df.select(exprs)
+    }
+
+    pub(crate) fn exprs(&self) -> Vec<Expr> {
+        self.components
+            .iter()
+            .enumerate()
+            .map(|(i, component)| {
+                self.columns
+                    .iter()
+                    .zip(&self.mean)
+                    .zip(component)
+                    .map(|((c, m), w)| (col(c).cast(DataType::Float64) - lit(*m)) * lit(*w))
+                    .reduce(|acc, e| acc + e)
+                    .unwrap()
+                    .alias(format!("pc{}", i).as_str())
+            })
+            .collect()
+    }
+
+    pub fn components(&self) -> &[Vec<f64>] {
+        &self.components
+    }
+
+    /// Share of the total variance captured by each kept component.
+    pub fn explained_variance_ratio(&self) -> &[f64] {
+        &self.explained_variance_ratio
+    }
+}
@@ -0,0 +1,102 @@
+use polars::prelude::*;
+
+/// How feature columns are rescaled before clustering.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Scaling {
+    /// Centre on the mean and divide by the (population) standard deviation.
+    Standard,
+    /// Map the observed range onto `[0, 1]`.
+    MinMax,
+    /// Centre on the median and divide by the interquartile range, which
+    /// outliers barely move.
+    Robust,
+}
+
+/// Per-column affine rescaling `(x - offset) / scale` fitted on a frame.
+///
+/// A column with no spread under the chosen statistic (a constant column,
+/// or a zero IQR for [`Scaling::Robust`]) gets a scale of 1 and is only
+/// shifted.
+#[derive(Clone, Debug)]
+pub struct Scaler {
+    scaling: Scaling,
+    columns: Vec<String>,
+    offset: Vec<f64>,
+    scale: Vec<f64>,
+}
+
+impl Scaler {
+    pub fn fit(df: LazyFrame, columns: &[String], scaling: Scaling) -> Self {
+        let stats = columns
+            .iter()
+            .flat_map(|c| {
+                let x = col(c).cast(DataType::Float64);
+                let (offset, spread) = match scaling {
+                    Scaling::Standard => (x.clone().mean(), x.std(0)),
+                    Scaling::MinMax => (x.clone().min(), x.clone().max() - x.min()),
+                    Scaling::Robust => (
+                        x.clone()
+                            .quantile(lit(0.5), QuantileInterpolOptions::Linear),
+                        x.clone()
+                            .quantile(lit(0.75), QuantileInterpolOptions::Linear)
+                            - x.quantile(lit(0.25), QuantileInterpolOptions::Linear),
+                    ),
+                };
+
+                [
+                    offset.alias(&format!("{c}_offset")),
+                    spread.alias(&format!("{c}_scale")),
+                ]
+            })
+            .collect::<Vec<_>>();
+
+        let row = df.select(stats).collect().unwrap();
+        let row = row
+            .get(0)
+            .unwrap()
+            .into_iter()
+            .map(|v| v.try_extract::<f64>().unwrap_or(0.0))
+            .collect::<Vec<_>>();
+
+        Scaler {
+            scaling,
+            columns: columns.to_vec(),
+            offset: row.iter().step_by(2).copied().collect(),
+            scale: row
+                .iter()
+                .skip(1)
+                .step_by(2)
+                .map(|s| if *s > 0.0 { *s } else { 1.0 })
+                .collect(),
+        }
+    }
+
+    /// Rescales the fitted columns in place, leaving other columns as is.
+    pub fn transform(&self, df: LazyFrame) -> LazyFrame {
+        df.with_columns(self.exprs())
+    }
+
+    pub(crate) fn exprs(&self) -> Vec<Expr> {
+        self.columns
+            .iter()
+            .zip(&self.offset)
+            .zip(&self.scale)
+            .map(|((c, o), s)| ((col(c).cast(DataType::Float64) - lit(*o)) / lit(*s)).alias(c))
+            .collect()
+    }
+
+    /// The statistic the scaler was fitted with.
+    pub fn scaling(&self) -> Scaling {
+        self.scaling
+    }
+
+    /// Value subtracted from each column: mean, minimum or median.
+    pub fn offset(&self) -> &[f64] {
+        &self.offset
+    }
+
+    /// Divisor applied to each column after the offset.
+    pub fn scale(&self) -> &[f64] {
+        &self.scale
+    }
+}
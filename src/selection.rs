@@ -0,0 +1,167 @@
+use std::ops::RangeInclusive;
+
+use polars::prelude::*;
+use rayon::prelude::*;
+
+use crate::{FitResult, KMeans, SelectionMetric};
+
+/// Fits every `k` in `k_range` and returns the one scoring best under
+/// `metric`, together with the score of every `k` that was evaluated. Each
+/// fit is seeded with `seed + k`. See [`sweep_k`] for details.
+pub fn select_k_by_metric(
+    df: LazyFrame,
+    k_range: RangeInclusive<usize>,
+    metric: SelectionMetric,
+    seed: Option<u64>,
+) -> (usize, Vec<(usize, f64)>) {
+    sweep_k(k_range, metric, false, |k| {
+        let kmeans = KMeans::new(df.clone(), k, None, false, CsvWriterOptions::default()).unwrap();
+        match seed {
+            Some(seed) => kmeans.with_seed(seed + k as u64),
+            None => kmeans,
+        }
+    })
+}
+
+/// Fits `new_kmeans(k)` for every `k` in `k_range` and returns the `k` with
+/// the highest `metric` score plus the score of every evaluated `k`. A `k`
+/// whose fit ends with fewer clusters is reported on stderr and left out.
+/// Each fit is scored as it finishes (see [`FitResult::selection_score`]),
+/// on its held-out rows when it has a validation split (see
+/// [`KMeans::with_validation_frac`]). `metric` also picks among restarts.
+///
+/// The fits run in parallel. With `warm_start` they run in order instead,
+/// each `k` starting from the previous fit with its largest cluster split
+/// (see [`FitResult::split_largest`]); this gives nested clusterings that
+/// differ from independent fits.
+///
+/// Panics if no `k` kept its cluster count.
+pub fn sweep_k(
+    k_range: RangeInclusive<usize>,
+    metric: SelectionMetric,
+    warm_start: bool,
+    new_kmeans: impl Fn(usize) -> KMeans + Sync,
+) -> (usize, Vec<(usize, f64)>) {
+    let fits = if warm_start {
+        let mut previous: Option<FitResult> = None;
+        k_range
+            .map(|k| {
+                let mut kmeans = new_kmeans(k).with_selection(metric);
+                if let Some(previous) = &previous {
+                    kmeans = kmeans.with_initial_centers(previous.split_largest());
+                }
+
+                let result = kmeans.fit();
+                previous = Some(result.clone());
+
+                (k, result)
+            })
+            .collect::<Vec<_>>()
+    } else {
+        k_range
+            .into_par_iter()
+            .map(|k| (k, new_kmeans(k).with_selection(metric).fit()))
+            .collect::<Vec<_>>()
+    };
+
+    let mut scores = Vec::new();
+    for (k, result) in fits {
+        if result.n_clusters_found() != result.n_clusters_requested() {
+            eprintln!(
+                "warning: k = {k} decreased to {} clusters and is skipped",
+                result.n_clusters_found()
+            );
+        } else {
+            scores.push((k, result.selection_score.unwrap()));
+        }
+    }
+
+    let mut best: Option<(usize, f64)> = None;
+    for &(k, score) in &scores {
+        if best.is_none_or(|(_, best)| score > best) {
+            best = Some((k, score));
+        }
+    }
+    let (k, _) = best.expect("every k in the sweep lost clusters");
+
+    (k, scores)
+}
+
+/// Smallest normalised distance from the chord that [`find_knee`] accepts
+/// as a knee; flatter curves fall back to their smallest `k`.
+const KNEE_MIN_DISTANCE: f64 = 0.05;
+
+/// Elbow of a score-vs-`k` curve such as inertia, by the Kneedle method:
+/// both axes are scaled to `[0, 1]` and the knee is the point farthest from
+/// the chord joining the first and last points. Points may come in any
+/// order and non-finite scores are ignored. Curves with fewer than three
+/// points, or too straight to have a clear knee, return their smallest `k`.
+///
+/// Panics if `points` has no finite score.
+pub fn find_knee(points: &[(usize, f64)]) -> usize {
+    let mut points = points
+        .iter()
+        .copied()
+        .filter(|(_, y)| y.is_finite())
+        .collect::<Vec<_>>();
+    points.sort_by_key(|(k, _)| *k);
+    let (first, last) = match (points.first(), points.last()) {
+        (Some(first), Some(last)) => (*first, *last),
+        _ => panic!("find_knee needs at least one finite score"),
+    };
+
+    let (y_min, y_max) = points
+        .iter()
+        .fold((f64::MAX, f64::MIN), |(lo, hi), (_, y)| {
+            (lo.min(*y), hi.max(*y))
+        });
+    if points.len() < 3 || last.0 == first.0 || y_max == y_min {
+        return first.0;
+    }
+
+    let x_span = (last.0 - first.0) as f64;
+    let normalised =
+        |(k, y): (usize, f64)| ((k - first.0) as f64 / x_span, (y - y_min) / (y_max - y_min));
+
+    let (x0, y0) = normalised(first);
+    let (x1, y1) = normalised(last);
+    let length = (x1 - x0).hypot(y1 - y0);
+    let (k, distance) = points
+        .iter()
+        .map(|&(k, y)| {
+            let (x, y) = normalised((k, y));
+            (
+                k,
+                ((x1 - x0) * (y0 - y) - (x0 - x) * (y1 - y0)).abs() / length,
+            )
+        })
+        .max_by(|(_, a), (_, b)| a.total_cmp(b))
+        .unwrap();
+
+    if distance < KNEE_MIN_DISTANCE {
+        first.0
+    } else {
+        k
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sweep_scores_every_k() {
+        let x = [0.0, 0.5, 1.0, 20.0, 20.5, 21.0, 40.0, 40.5, 41.0];
+        let df = df!("n" => (0..9u64).collect::<Vec<_>>(), "x" => x)
+            .unwrap()
+            .lazy();
+        let (k, scores) = select_k_by_metric(df, 2..=5, SelectionMetric::Silhouette, Some(1));
+
+        assert_eq!(k, 3);
+        assert_eq!(
+            scores.iter().map(|(k, _)| *k).collect::<Vec<_>>(),
+            [2, 3, 4, 5]
+        );
+        assert!(scores.iter().all(|(_, score)| score.is_finite()));
+    }
+}
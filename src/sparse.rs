@@ -0,0 +1,98 @@
+use polars::prelude::*;
+
+use crate::{distance::KL_EPSILON, DistanceMetric};
+
+/// Feature rows in compressed sparse row form, keeping only non-zero values
+/// so that distances cost one pass over the non-zeros per centroid.
+#[derive(Clone, Debug)]
+pub(crate) struct SparseRows {
+    offsets: Vec<usize>,
+    indices: Vec<usize>,
+    values: Vec<f64>,
+    norms: Vec<f64>,
+}
+
+impl SparseRows {
+    /// Builds the rows from `features`, reading one column at a time.
+    pub(crate) fn from_frame(df: &LazyFrame, features: &[String]) -> Self {
+        let mut rows = Vec::<Vec<(usize, f64)>>::new();
+        for (j, f) in features.iter().enumerate() {
+            let column = df
+                .clone()
+                .select([col(f).cast(DataType::Float64)])
+                .collect()
+                .unwrap();
+            let column = column.column(f).unwrap().f64().unwrap();
+            rows.resize(column.len(), Vec::new());
+
+            for (row, x) in rows
+                .iter_mut()
+                .zip(column.into_iter().map(|x| x.unwrap_or(f64::NAN)))
+            {
+                if x != 0.0 {
+                    row.push((j, x));
+                }
+            }
+        }
+
+        Self::from_rows(rows)
+    }
+
+    fn from_rows(rows: Vec<Vec<(usize, f64)>>) -> Self {
+        let mut sparse = SparseRows {
+            offsets: vec![0],
+            indices: Vec::new(),
+            values: Vec::new(),
+            norms: Vec::new(),
+        };
+
+        for row in rows {
+            sparse.norms.push(row.iter().map(|(_, x)| x * x).sum());
+            for (j, x) in row {
+                sparse.indices.push(j);
+                sparse.values.push(x);
+            }
+            sparse.offsets.push(sparse.indices.len());
+        }
+
+        sparse
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.norms.len()
+    }
+
+    /// Distance from every row to each centroid, one `Vec` per centroid.
+    /// Euclidean distance uses `|x|² - 2 x·c + |c|²`; KL divergence only has
+    /// terms where `x` is non-zero.
+    pub(crate) fn distances(&self, centers: &[Vec<f64>], metric: DistanceMetric) -> Vec<Vec<f64>> {
+        centers
+            .iter()
+            .map(|center| {
+                let center_norm = center.iter().map(|c| c * c).sum::<f64>();
+
+                (0..self.len())
+                    .map(|i| {
+                        let range = self.offsets[i]..self.offsets[i + 1];
+                        let entries = self.indices[range.clone()]
+                            .iter()
+                            .zip(&self.values[range])
+                            .map(|(j, x)| (center[*j], *x));
+
+                        match metric {
+                            DistanceMetric::Euclidean | DistanceMetric::RangeNormalized => {
+                                let dot = entries.map(|(c, x)| c * x).sum::<f64>();
+                                // Cancellation can dip just below zero for a
+                                // point sitting on the centroid.
+                                (self.norms[i] - 2.0 * dot + center_norm).max(0.0).sqrt()
+                            }
+                            DistanceMetric::KLDivergence => entries
+                                .map(|(c, x)| x * ((x + KL_EPSILON) / (c + KL_EPSILON)).ln())
+                                .sum(),
+                        }
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,153 @@
+use std::f64::consts::PI;
+
+use polars::prelude::*;
+
+use crate::{metrics::points, Error, KMeans};
+
+/// Chooses `k` automatically by starting from `k_min` clusters and splitting
+/// each cluster in two whenever the split improves the Bayesian information
+/// criterion under a spherical Gaussian model, until no split helps or
+/// `k_max` is reached. Returns the clusters, renumbered from zero, and the
+/// selected `k`. With a `seed`, the initial fit uses it and every later
+/// 2-means fit the next value, so the search is reproducible.
+///
+/// Fails with the errors of [`KMeans::new`] and [`KMeans::try_eval`], for
+/// the initial fit as for every split.
+pub fn xmeans(
+    df: LazyFrame,
+    k_min: usize,
+    k_max: usize,
+    seed: Option<u64>,
+) -> Result<(Vec<(u64, LazyFrame)>, usize), Error> {
+    let mut seeds = seed.map(|seed| seed..);
+    let mut next_seed = move || seeds.as_mut().and_then(Iterator::next);
+
+    let mut clusters = fit(df, k_min, next_seed())?
+        .into_iter()
+        .map(|(_, lf)| lf)
+        .collect::<Vec<_>>();
+
+    loop {
+        let mut next = Vec::new();
+        let mut split = false;
+
+        for (i, lf) in clusters.iter().enumerate() {
+            let remaining = clusters.len() - i - 1;
+            if next.len() + remaining + 2 > k_max {
+                next.push(lf.clone());
+                continue;
+            }
+
+            let children = fit(lf.clone(), 2, next_seed())?
+                .into_iter()
+                .map(|(_, lf)| lf)
+                .collect::<Vec<_>>();
+            if children.len() == 2 {
+                let parent = bic(&[points(lf)]);
+                let child = bic(&children.iter().map(points).collect::<Vec<_>>());
+
+                if child > parent {
+                    next.extend(children);
+                    split = true;
+                    continue;
+                }
+            }
+
+            next.push(lf.clone());
+        }
+
+        clusters = next;
+
+        if !split || clusters.len() >= k_max {
+            break;
+        }
+    }
+
+    let k = clusters.len();
+    let clusters = clusters
+        .into_iter()
+        .enumerate()
+        .map(|(i, lf)| (i as u64, lf))
+        .collect();
+
+    Ok((clusters, k))
+}
+
+fn fit(lf: LazyFrame, k: usize, seed: Option<u64>) -> Result<Vec<(u64, LazyFrame)>, Error> {
+    let kmeans = KMeans::new(lf, k, None, false, CsvWriterOptions::default())?;
+    match seed {
+        Some(seed) => kmeans.with_seed(seed),
+        None => kmeans,
+    }
+    .try_eval()
+}
+
+/// BIC of a clustering following Pelleg & Moore, with a single variance
+/// shared by all clusters.
+fn bic(clusters: &[Vec<Vec<f64>>]) -> f64 {
+    let k = clusters.len() as f64;
+    let r = clusters.iter().map(Vec::len).sum::<usize>() as f64;
+    let m = clusters[0][0].len() as f64;
+
+    let mut sse = 0.0;
+    for points in clusters {
+        let mean = (0..points[0].len())
+            .map(|j| points.iter().map(|p| p[j]).sum::<f64>() / points.len() as f64)
+            .collect::<Vec<_>>();
+
+        sse += points
+            .iter()
+            .map(|p| {
+                p.iter()
+                    .zip(&mean)
+                    .map(|(x, c)| (x - c).powi(2))
+                    .sum::<f64>()
+            })
+            .sum::<f64>();
+    }
+
+    let variance = (sse / (r - k).max(1.0) / m).max(f64::EPSILON);
+
+    let log_likelihood = clusters
+        .iter()
+        .map(|points| {
+            let rn = points.len() as f64;
+            rn * rn.ln()
+                - rn * r.ln()
+                - rn / 2.0 * (2.0 * PI).ln()
+                - rn * m / 2.0 * variance.ln()
+                - (rn - k) / 2.0
+        })
+        .sum::<f64>();
+
+    let parameters = (k - 1.0) + m * k + 1.0;
+
+    log_likelihood - parameters / 2.0 * r.ln()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::make_blobs;
+
+    #[test]
+    fn recovers_separated_blobs() {
+        for seed in 0..5 {
+            let (df, _) = make_blobs(300, 2, 3, 0.5, 4);
+            let (clusters, k) = xmeans(df, 1, 8, Some(seed)).unwrap();
+
+            assert_eq!(k, 3);
+            assert_eq!(clusters.len(), 3);
+        }
+    }
+
+    #[test]
+    fn stops_at_k_max() {
+        let (df, _) = make_blobs(300, 2, 6, 0.5, 4);
+        let (clusters, k) = xmeans(df, 1, 4, Some(1)).unwrap();
+
+        assert_eq!(k, 4);
+        let ids: Vec<u64> = clusters.iter().map(|(id, _)| *id).collect();
+        assert_eq!(ids, [0, 1, 2, 3]);
+    }
+}